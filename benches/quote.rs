@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::fs;
+use std::hint::black_box;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use jupiter_amm_integration::amm::{
+    SolayerEndoAVSAmm, SolayerEndoAVSAmmBuilder, AVS_MINT_TO_ENDO_AVS_ADDRESS, BYBIT_AVS_ADDRESS, BYBIT_AVS_MINT,
+    OKX_AVS_ADDRESS, OKX_AVS_MINT, SOLAYER_SOL,
+};
+use jupiter_amm_interface::{
+    Amm, AmmContext, ClockRef, KeyedAccount, KeyedUiAccount, QuoteParams, SwapMode, SwapParams,
+};
+use solana_sdk::pubkey::Pubkey;
+
+// Same synthetic fixture as the integration tests, sSOL paired with the Bybit AVS mint. Both AVS
+// are registered explicitly so the bench also runs without the built-in mainnet set.
+fn bybit_amm() -> SolayerEndoAVSAmm {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/synthetic_endo_avs_pool.json");
+    let keyed_ui_account: KeyedUiAccount = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    let keyed_account: KeyedAccount = keyed_ui_account.try_into().unwrap();
+    let amm_context = AmmContext {
+        clock_ref: ClockRef::default(),
+    };
+    SolayerEndoAVSAmmBuilder::new()
+        .avs_overrides(HashMap::from([
+            (BYBIT_AVS_MINT, BYBIT_AVS_ADDRESS),
+            (OKX_AVS_MINT, OKX_AVS_ADDRESS),
+        ]))
+        .build(&keyed_account, &amm_context)
+        .unwrap()
+}
+
+fn bench_quote(c: &mut Criterion) {
+    let amm = bybit_amm();
+    for (name, swap_mode) in [("quote_exact_in", SwapMode::ExactIn), ("quote_exact_out", SwapMode::ExactOut)] {
+        let quote_params = QuoteParams {
+            amount: 1_000_000_000,
+            input_mint: SOLAYER_SOL,
+            output_mint: BYBIT_AVS_MINT,
+            swap_mode,
+        };
+        c.bench_function(name, |b| b.iter(|| amm.quote(black_box(&quote_params)).unwrap()));
+    }
+}
+
+fn bench_swap_and_account_metas(c: &mut Criterion) {
+    let amm = bybit_amm();
+    let jupiter_program_id = Pubkey::new_unique();
+    let directions = [
+        ("metas_delegate", SOLAYER_SOL, BYBIT_AVS_MINT),
+        ("metas_undelegate", BYBIT_AVS_MINT, SOLAYER_SOL),
+    ];
+    for (name, source_mint, destination_mint) in directions {
+        let swap_params = SwapParams {
+            swap_mode: SwapMode::ExactIn,
+            in_amount: 1_000_000_000,
+            out_amount: 1_000_000_000,
+            source_mint,
+            destination_mint,
+            source_token_account: Pubkey::new_unique(),
+            destination_token_account: Pubkey::new_unique(),
+            token_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            jupiter_program_id: &jupiter_program_id,
+            missing_dynamic_accounts_as_default: false,
+        };
+        c.bench_function(name, |b| {
+            b.iter(|| amm.get_swap_and_account_metas(black_box(&swap_params)).unwrap())
+        });
+    }
+}
+
+// The global map against the AMM's lookup, cached for its own AVS mint and mapped for others
+fn bench_avs_lookup(c: &mut Criterion) {
+    let amm = bybit_amm();
+    c.bench_function("avs_mint_lookup", |b| {
+        b.iter(|| AVS_MINT_TO_ENDO_AVS_ADDRESS.get(black_box(&BYBIT_AVS_MINT)))
+    });
+    c.bench_function("endo_avs_address_own_mint", |b| {
+        b.iter(|| amm.endo_avs_address(black_box(&BYBIT_AVS_MINT)).unwrap())
+    });
+    c.bench_function("endo_avs_address_other_mint", |b| {
+        b.iter(|| amm.endo_avs_address(black_box(&OKX_AVS_MINT)).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_quote, bench_swap_and_account_metas, bench_avs_lookup);
+criterion_main!(benches);
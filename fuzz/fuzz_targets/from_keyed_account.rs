@@ -0,0 +1,27 @@
+#![no_main]
+
+use jupiter_amm_integration::amm::{SolayerEndoAVSAmm, SOLAYER_DELEGATION_PROGRAM};
+use jupiter_amm_interface::{Amm, AmmContext, ClockRef, KeyedAccount};
+use libfuzzer_sys::fuzz_target;
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+
+// Arbitrary swap account data must only ever produce Ok or Err
+fuzz_target!(|data: &[u8]| {
+    let keyed_account = KeyedAccount {
+        key: Pubkey::default(),
+        account: Account {
+            lamports: 0,
+            data: data.to_vec(),
+            // owned by the delegation program so the data gets past the owner check
+            owner: SOLAYER_DELEGATION_PROGRAM,
+            executable: false,
+            rent_epoch: 0,
+        },
+        params: None,
+    };
+    let amm_context = AmmContext {
+        clock_ref: ClockRef::default(),
+    };
+    let _ = SolayerEndoAVSAmm::from_keyed_account(&keyed_account, &amm_context);
+});
@@ -1,2 +0,0 @@
-pub mod amms;
-pub use amms::amm;
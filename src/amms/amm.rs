@@ -1,15 +1,42 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use jupiter_amm_interface::{
-    AccountMap, Amm, AmmContext, KeyedAccount, Quote, QuoteParams, Swap, SwapAndAccountMetas,
-    SwapParams,
+    AccountMap, Amm, AmmContext, ClockRef, KeyedAccount, Quote, QuoteParams, Swap,
+    SwapAndAccountMetas, SwapMode, SwapParams,
 };
-use solana_sdk::instruction::AccountMeta;
+use rust_decimal::Decimal;
+#[cfg(feature = "rpc")]
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::program_option::COption;
+use solana_sdk::program_pack::Pack as _;
 use solana_sdk::{pubkey, pubkey::Pubkey};
-use spl_associated_token_account::get_associated_token_address;
+use solana_sdk::rent::Rent;
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token::state::Account as TokenAccount;
+use spl_token_2022::extension::{ExtensionType, StateWithExtensions};
+use spl_token_2022::state::{Account as Token2022Account, Mint};
 use spl_token_swap::solana_program::program_pack::Pack;
-use spl_token_swap::{curve::base::SwapCurve, state::SwapV1};
+use spl_token_swap::curve::fees::Fees;
+use spl_token_swap::state::SwapV1;
+use tracing::{debug, debug_span, warn};
+
+use crate::amms::error::SolayerAmmError;
+use crate::amms::instruction;
+
+/// Program owning the endoAVS swap accounts on mainnet
+///
+/// `SolayerEndoAVSAmmBuilder::build` rejects accounts owned by any other program unless the
+/// builder is given another one, so filtering accounts by this owner first skips them cheaply.
+pub const SOLAYER_DELEGATION_PROGRAM: Pubkey = pubkey!("endoLNCKTqDn8gSVnN2hDdpgACUPWHZTwoYnnMybpAT");
 
 pub const SOLAYER_SOL: Pubkey = pubkey!("sSo14endRuUbvQaJS3dq36Q829a3A6BEfoeeRGJywEh");
 pub const BYBIT_AVS_MINT: Pubkey = pubkey!("bybitztPBdV3KRMfwr8ysBf7xx15JvwvuNWa7Wj9Wgz");
@@ -30,185 +57,4464 @@ pub const HASH_KEY_CLOUD_AVS_ADDRESS: Pubkey = pubkey!("745mkVyUsYe6FrSujnKQGiaL
 pub const BONK_AVS_ADDRESS: Pubkey = pubkey!("E2VVTVBeaV8U197Mnvpa9skjaxPDDiHeTpGK1CkvW6fL");
 pub const ALT_LAYER_AVS_ADDRESS: Pubkey = pubkey!("EBYsvMRRYnjbeGQ91mruwTBx8C4vtC8nUFhCGX4xmgHX");
 
+/// A built-in AVS: its token mint, endoAVS address and display name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SupportedAvs {
+    pub mint: Pubkey,
+    pub address: Pubkey,
+    pub name: &'static str,
+}
+
+// Source of truth for the built-in AVS, every map below is built from it
+pub const SUPPORTED_AVS: [SupportedAvs; 8] = [
+    SupportedAvs { mint: BYBIT_AVS_MINT, address: BYBIT_AVS_ADDRESS, name: "Bybit" },
+    SupportedAvs { mint: OKX_AVS_MINT, address: OKX_AVS_ADDRESS, name: "OKX" },
+    SupportedAvs { mint: KAMINO_FINANCE_AVS_MINT, address: KAMINO_FINANCE_AVS_ADDRESS, name: "Kamino Finance" },
+    SupportedAvs { mint: BITGET_AVS_MINT, address: BITGET_AVS_ADDRESS, name: "Bitget" },
+    SupportedAvs { mint: SONIC_AVS_MINT, address: SONIC_AVS_ADDRESS, name: "Sonic" },
+    SupportedAvs { mint: HASH_KEY_CLOUD_AVS_MINT, address: HASH_KEY_CLOUD_AVS_ADDRESS, name: "HashKey Cloud" },
+    SupportedAvs { mint: BONK_AVS_MINT, address: BONK_AVS_ADDRESS, name: "Bonk" },
+    SupportedAvs { mint: ALT_LAYER_AVS_MINT, address: ALT_LAYER_AVS_ADDRESS, name: "AltLayer" },
+];
+
+// The default AVS set, empty without the mainnet-avs feature so non-mainnet builds only use
+// mappings registered through the builder
+const fn built_in_avs() -> &'static [SupportedAvs] {
+    if cfg!(feature = "mainnet-avs") {
+        &SUPPORTED_AVS
+    } else {
+        &[]
+    }
+}
+
 lazy_static::lazy_static! {
-    pub static ref AVS_MINT_TO_ENDO_AVS_ADDRESS: HashMap<Pubkey, Pubkey> ={
-        let mut m = HashMap::new();
-        m.insert(BYBIT_AVS_MINT, BYBIT_AVS_ADDRESS);
-        m.insert(OKX_AVS_MINT, OKX_AVS_ADDRESS);
-        m.insert(KAMINO_FINANCE_AVS_MINT, KAMINO_FINANCE_AVS_ADDRESS);
-        m.insert(BITGET_AVS_MINT, BITGET_AVS_ADDRESS);
-        m.insert(SONIC_AVS_MINT, SONIC_AVS_ADDRESS);
-        m.insert(HASH_KEY_CLOUD_AVS_MINT, HASH_KEY_CLOUD_AVS_ADDRESS);
-        m.insert(BONK_AVS_MINT, BONK_AVS_ADDRESS);
-        m.insert(ALT_LAYER_AVS_MINT, ALT_LAYER_AVS_ADDRESS);
-        m
+    pub static ref AVS_MINT_TO_ENDO_AVS_ADDRESS: HashMap<Pubkey, Pubkey> =
+        built_in_avs().iter().map(|avs| (avs.mint, avs.address)).collect();
+    pub static ref ENDO_AVS_ADDRESS_TO_AVS_MINT: HashMap<Pubkey, Pubkey> =
+        built_in_avs().iter().map(|avs| (avs.address, avs.mint)).collect();
+}
+
+// Version byte prefixed to SwapV1 account data
+pub const SWAP_V1_VERSION: u8 = 1;
+
+// Seed prefix of the endoAVS PDA, derived as [ENDO_AVS_SEED, avs_mint] under the delegation program
+pub const ENDO_AVS_SEED: &[u8] = b"endo_avs";
+
+/// The endoAVS PDA that `program_id` derives for `avs_mint`
+pub fn derive_endo_avs_address(avs_mint: &Pubkey, program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[ENDO_AVS_SEED, avs_mint.as_ref()], program_id).0
+}
+
+/// Checks that the built-in endoAVS address for `avs_mint` is the PDA `program_id` derives for it
+pub fn verify_endo_avs_address(avs_mint: &Pubkey, program_id: &Pubkey) -> Result<(), SolayerAmmError> {
+    let address = *AVS_MINT_TO_ENDO_AVS_ADDRESS
+        .get(avs_mint)
+        .ok_or(SolayerAmmError::UnknownAvsMint(*avs_mint))?;
+    let derived = derive_endo_avs_address(avs_mint, program_id);
+    if address != derived {
+        return Err(SolayerAmmError::EndoAvsAddressMismatch {
+            avs_mint: *avs_mint,
+            address,
+            derived,
+        });
+    }
+    Ok(())
+}
+
+// Rough compute unit budgets, undelegating also signs for the vault with the endoAVS PDA
+pub const DELEGATE_COMPUTE_UNITS: u32 = 35_000;
+pub const UNDELEGATE_COMPUTE_UNITS: u32 = 40_000;
+
+// Basis points in a whole, the largest fee `SolayerEndoAVSAmmBuilder::avs_fee_bps` accepts
+pub const MAX_BPS: u16 = 10_000;
+
+// Number of accounts passed to both the delegate and undelegate instructions
+pub const SWAP_ACCOUNTS_LEN: usize = 8;
+// Writable flags of the swap accounts, in the order `build_account_metas` lays them out
+const SWAP_ACCOUNTS_WRITABLE: [bool; SWAP_ACCOUNTS_LEN] = [false, false, true, true, false, true, true, false];
+// Accounts creating the destination ATA adds on top of the swap: owner, system program and
+// associated token program, the ATA, mint and token program are already in the swap
+pub const ATA_CREATION_ACCOUNTS_LEN: usize = 3;
+
+/// Which way a swap moves tokens between sSOL and an AVS token
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapDirection {
+    /// sSOL to AVS token
+    Delegate,
+    /// AVS token to sSOL
+    Undelegate,
+}
+
+impl SwapDirection {
+    fn from_source_mint(source_mint: &Pubkey, ssol_mint: &Pubkey) -> Self {
+        if source_mint == ssol_mint {
+            SwapDirection::Delegate
+        } else {
+            SwapDirection::Undelegate
+        }
+    }
+
+    fn reverse(self) -> Self {
+        match self {
+            SwapDirection::Delegate => SwapDirection::Undelegate,
+            SwapDirection::Undelegate => SwapDirection::Delegate,
+        }
+    }
+
+    fn swap(self) -> Swap {
+        match self {
+            SwapDirection::Delegate => Swap::SolayerDelegateNoInit,
+            SwapDirection::Undelegate => Swap::SolayerUndelegateNoInit,
+        }
+    }
+
+    fn instruction_data(self, amount: u64) -> Vec<u8> {
+        match self {
+            SwapDirection::Delegate => instruction::delegate_data(amount),
+            SwapDirection::Undelegate => instruction::undelegate_data(amount),
+        }
+    }
+}
+
+/// AVS tokens received per sSOL delegated, as `numerator / denominator`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExchangeRate {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl ExchangeRate {
+    // A zero on either side would zero out or divide by zero in `convert`
+    fn validate(&self) -> Result<(), SolayerAmmError> {
+        if self.numerator == 0 || self.denominator == 0 {
+            return Err(SolayerAmmError::InvalidExchangeRate {
+                numerator: self.numerator,
+                denominator: self.denominator,
+            });
+        }
+        Ok(())
+    }
+
+    // Converts `amount` of the source side of `direction` into the destination side
+    fn convert(self, amount: u64, direction: SwapDirection, round_up: bool) -> Result<u64, SolayerAmmError> {
+        let (multiplier, divisor) = match direction {
+            SwapDirection::Delegate => (self.numerator, self.denominator),
+            SwapDirection::Undelegate => (self.denominator, self.numerator),
+        };
+        let product = u128::from(amount) * u128::from(multiplier);
+        let divisor = u128::from(divisor);
+        let converted = if round_up {
+            product.div_ceil(divisor)
+        } else {
+            product / divisor
+        };
+        u64::try_from(converted).map_err(|_| SolayerAmmError::MathOverflow("exchange rate"))
+    }
+}
+
+/// How `quote` answers a mint the pool doesn't trade
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum UnknownMintPolicy {
+    /// Fail with `SolayerAmmError::UnknownAvsMint` or `UnsupportedMintPair`
+    #[default]
+    Error,
+    /// Return an all-zero quote, marking the pair as not routable
+    Skip,
+}
+
+/// How building answers a pool with sSOL on neither side, a misconfigured endoAVS pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissingSsolPolicy {
+    /// Log a warning and build the pool anyway
+    #[default]
+    Warn,
+    /// Fail with `SolayerAmmError::MissingSsolReserve`
+    Error,
+}
+
+/// What changed between two polls of a pool, each field `Some((before, after))` when it did
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AmmDiff {
+    pub reserves: Option<([u128; 2], [u128; 2])>,
+    pub exchange_rate: Option<(Option<ExchangeRate>, Option<ExchangeRate>)>,
+    /// Trade fee as (numerator, denominator)
+    pub trade_fee: Option<((u64, u64), (u64, u64))>,
+}
+
+impl AmmDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// What is known of a pool's liquidity, see `SolayerEndoAVSAmm::liquidity_state`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiquidityState {
+    /// `update` hasn't loaded the reserves yet
+    Unknown,
+    /// At least one side holds tokens, so at least one direction can be quoted
+    Available,
+    /// Both reserves are empty
+    Empty,
+}
+
+/// Swap metas along with how they were resolved, see `get_swap_and_account_metas_detailed`
+#[derive(Clone, Debug)]
+pub struct DetailedSwapAndAccountMetas {
+    pub direction: SwapDirection,
+    pub endo_avs: Pubkey,
+    pub swap: Swap,
+    pub account_metas: Vec<AccountMeta>,
+}
+
+/// A delegate or undelegate swap checked against a pool, with each account in its role
+///
+/// `get_swap_and_account_metas` builds the metas from it, so everything read off `SwapParams`
+/// is validated in one place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SolayerSwapRequest {
+    pub direction: SwapDirection,
+    pub endo_avs: Pubkey,
+    /// Transfer authority over the staker token accounts, see `validate_transfer_authority`
+    pub staker: Pubkey,
+    /// sSOL
+    pub delegated_token_mint: Pubkey,
+    pub avs_token_mint: Pubkey,
+    pub staker_delegated_token_account: Pubkey,
+    pub staker_avs_token_account: Pubkey,
+    pub in_amount: u64,
+}
+
+impl SolayerSwapRequest {
+    /// Checks that `swap_params` pairs sSOL with the AVS mint of `amm`'s pool, moves a non-zero
+    /// amount and names both token accounts, then resolves the direction and endoAVS
+    pub fn from_swap_params(amm: &SolayerEndoAVSAmm, swap_params: &SwapParams) -> Result<Self, SolayerAmmError> {
+        amm.validate_mint_pair(&swap_params.source_mint, &swap_params.destination_mint)?;
+        Self::from_checked_mints(amm, swap_params)
+    }
+
+    // One leg of an AVS -> AVS swap, whose AVS mint may be another pool's
+    fn from_avs_to_avs_leg(amm: &SolayerEndoAVSAmm, swap_params: &SwapParams) -> Result<Self, SolayerAmmError> {
+        amm.validate_known_avs_pair(&swap_params.source_mint, &swap_params.destination_mint)?;
+        Self::from_checked_mints(amm, swap_params)
+    }
+
+    fn from_checked_mints(amm: &SolayerEndoAVSAmm, swap_params: &SwapParams) -> Result<Self, SolayerAmmError> {
+        if swap_params.in_amount == 0 {
+            return Err(SolayerAmmError::ZeroAmount);
+        }
+        if swap_params.source_token_account == Pubkey::default() {
+            return Err(SolayerAmmError::MissingTokenAccount {
+                side: "source",
+                mint: swap_params.source_mint,
+            });
+        }
+        if swap_params.destination_token_account == Pubkey::default() {
+            return Err(SolayerAmmError::MissingTokenAccount {
+                side: "destination",
+                mint: swap_params.destination_mint,
+            });
+        }
+
+        // sSOL is the delegated token, the other side is the AVS token
+        let direction = SwapDirection::from_source_mint(&swap_params.source_mint, &amm.ssol_mint);
+        let (delegated_token_mint, avs_token_mint, staker_delegated_token_account, staker_avs_token_account) =
+            match direction {
+                SwapDirection::Delegate => (
+                    swap_params.source_mint,
+                    swap_params.destination_mint,
+                    swap_params.source_token_account,
+                    swap_params.destination_token_account,
+                ),
+                SwapDirection::Undelegate => (
+                    swap_params.destination_mint,
+                    swap_params.source_mint,
+                    swap_params.destination_token_account,
+                    swap_params.source_token_account,
+                ),
+            };
+        Ok(Self {
+            direction,
+            endo_avs: amm.endo_avs_address(&avs_token_mint)?,
+            staker: swap_params.token_transfer_authority,
+            delegated_token_mint,
+            avs_token_mint,
+            staker_delegated_token_account,
+            staker_avs_token_account,
+            in_amount: swap_params.in_amount,
+        })
+    }
+}
+
+/// Swap account state, tagged with the layout version it was unpacked from
+///
+/// The delegation program only writes SwapV1 accounts today. A later layout gets a variant
+/// here and an arm in `unpack_swap_state`, the AMM only reads the state through the accessors.
+#[derive(Debug, PartialEq)]
+pub enum SwapState {
+    V1(SwapV1),
+}
+
+impl SwapState {
+    /// The version byte leading the account data
+    pub fn version(&self) -> u8 {
+        match self {
+            SwapState::V1(_) => SWAP_V1_VERSION,
+        }
+    }
+
+    pub fn token_a(&self) -> Pubkey {
+        match self {
+            SwapState::V1(state) => Pubkey::from(state.token_a.to_bytes()),
+        }
+    }
+
+    pub fn token_b(&self) -> Pubkey {
+        match self {
+            SwapState::V1(state) => Pubkey::from(state.token_b.to_bytes()),
+        }
+    }
+
+    pub fn token_a_mint(&self) -> Pubkey {
+        match self {
+            SwapState::V1(state) => Pubkey::from(state.token_a_mint.to_bytes()),
+        }
+    }
+
+    pub fn token_b_mint(&self) -> Pubkey {
+        match self {
+            SwapState::V1(state) => Pubkey::from(state.token_b_mint.to_bytes()),
+        }
+    }
+
+    pub fn pool_fee_account(&self) -> Pubkey {
+        match self {
+            SwapState::V1(state) => Pubkey::from(state.pool_fee_account.to_bytes()),
+        }
+    }
+
+    pub fn fees(&self) -> &Fees {
+        match self {
+            SwapState::V1(state) => &state.fees,
+        }
+    }
+
+    /// The account data this state unpacks from, version byte included
+    pub fn pack(&self) -> Vec<u8> {
+        match self {
+            SwapState::V1(state) => {
+                let mut data = vec![0; 1 + SwapV1::LEN];
+                data[0] = SWAP_V1_VERSION;
+                state.pack_into_slice(&mut data[1..]);
+                data
+            }
+        }
+    }
+}
+
+impl From<SwapV1> for SwapState {
+    fn from(state: SwapV1) -> Self {
+        SwapState::V1(state)
+    }
+}
+
+/// Unpacks swap account data, dispatching on its leading version byte
+///
+/// Versions without a `SwapState` variant are refused rather than misread as V1, and so is a
+/// pool whose `is_initialized` flag is unset.
+pub fn unpack_swap_state(key: &Pubkey, account_data: &[u8]) -> Result<SwapState, SolayerAmmError> {
+    let (&version, data) = account_data.split_first().ok_or(SolayerAmmError::EmptyAccountData)?;
+    match version {
+        SWAP_V1_VERSION => {
+            if data.len() < SwapV1::LEN {
+                return Err(SolayerAmmError::AccountDataTooShort {
+                    expected: 1 + SwapV1::LEN,
+                    actual: 1 + data.len(),
+                });
+            }
+            // unchecked so an uninitialized pool gets its own error rather than a generic one
+            let state = SwapV1::unpack_unchecked(data).map_err(|err| SolayerAmmError::InvalidAccountData {
+                account: *key,
+                reason: err.to_string(),
+            })?;
+            if !state.is_initialized {
+                return Err(SolayerAmmError::UninitializedPool(*key));
+            }
+            Ok(SwapState::V1(state))
+        }
+        _ => Err(SolayerAmmError::UnsupportedVersion {
+            version,
+            expected: SWAP_V1_VERSION,
+        }),
+    }
+}
+
+// e.g. "Bybit AVS", falling back to the mint for AVS without a name
+fn avs_display_name(avs_mint: &Pubkey) -> String {
+    match SUPPORTED_AVS.iter().find(|avs| avs.mint == *avs_mint) {
+        Some(avs) => format!("{} AVS", avs.name),
+        None => avs_mint.to_string(),
+    }
+}
+
+// Offsets of token_a_mint and token_b_mint in swap account data, the version byte included
+const TOKEN_A_MINT_OFFSET: usize = 1 + 130;
+const TOKEN_B_MINT_OFFSET: usize = 1 + 162;
+
+/// Cheap pre-filter for bulk scans: owned by the delegation program, a SwapV1 account and
+/// sSOL on one side, read straight from the mint fields without unpacking the whole state
+///
+/// Checks against the mainnet program and sSOL mint, see `is_endo_avs_account` for other deployments.
+pub fn is_solayer_endo_avs_account(account: &Account) -> bool {
+    is_endo_avs_account(account, &SOLAYER_DELEGATION_PROGRAM, &SOLAYER_SOL)
+}
+
+/// `is_solayer_endo_avs_account` for the delegation program and sSOL mint of another
+/// deployment, e.g. devnet
+pub fn is_endo_avs_account(account: &Account, program_id: &Pubkey, ssol_mint: &Pubkey) -> bool {
+    let data = &account.data;
+    if account.owner != *program_id || data.len() < 1 + SwapV1::LEN || data[0] != SWAP_V1_VERSION {
+        return false;
+    }
+    [TOKEN_A_MINT_OFFSET, TOKEN_B_MINT_OFFSET]
+        .iter()
+        .any(|offset| data[*offset..*offset + 32] == ssol_mint.to_bytes())
+}
+
+// Unpacks a token account of either token program, Token-2022 extensions included
+fn unpack_token_account(address: &Pubkey, data: &[u8]) -> Result<Token2022Account, SolayerAmmError> {
+    StateWithExtensions::<Token2022Account>::unpack(data)
+        .map(|state| state.base)
+        .map_err(|err| SolayerAmmError::InvalidAccountData {
+            account: *address,
+            reason: err.to_string(),
+        })
+}
+
+// Converts base units of a mint with `from_decimals` into one with `to_decimals`
+fn scale_decimals(amount: u64, from_decimals: u8, to_decimals: u8, round_up: bool) -> Result<u64, SolayerAmmError> {
+    if from_decimals == to_decimals {
+        return Ok(amount);
+    }
+    let overflow = SolayerAmmError::MathOverflow("decimal scaling");
+    let factor = 10u128
+        .checked_pow(u32::from(from_decimals.abs_diff(to_decimals)))
+        .ok_or(overflow.clone())?;
+    let scaled = if to_decimals > from_decimals {
+        u128::from(amount).checked_mul(factor).ok_or(overflow.clone())?
+    } else if round_up {
+        u128::from(amount).div_ceil(factor)
+    } else {
+        u128::from(amount) / factor
     };
+    u64::try_from(scaled).map_err(|_| overflow)
+}
+
+// Index of the AVS mint in a pool's reserve mints, the one that isn't `ssol_mint`
+fn avs_mint_index(reserve_mints: &[Pubkey; 2], ssol_mint: &Pubkey) -> usize {
+    if reserve_mints[0] == *ssol_mint {
+        1
+    } else {
+        0
+    }
+}
+
+/// Builds a `SolayerEndoAVSAmm` with settings that `Amm::from_keyed_account` can't carry
+#[derive(Clone, Default)]
+pub struct SolayerEndoAVSAmmBuilder {
+    avs_overrides: HashMap<Pubkey, Pubkey>,
+    avs_to_avs: bool,
+    label_prefix: String,
+    program_id: Option<Pubkey>,
+    activation_epoch: Option<u64>,
+    min_amount: Option<u64>,
+    max_amount: Option<u64>,
+    undelegation_cooldown_slots: Option<u64>,
+    unknown_mint_policy: UnknownMintPolicy,
+    token_programs: HashMap<Pubkey, Pubkey>,
+    avs_fee_bps: HashMap<Pubkey, u16>,
+    ssol_mint: Option<Pubkey>,
+    missing_ssol_policy: MissingSsolPolicy,
+    exchange_rate: Option<ExchangeRate>,
+}
+
+impl SolayerEndoAVSAmmBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Extra AVS mint -> endoAVS address mappings, merged on top of the built-in set
+    ///
+    /// An sSOL entry is dropped when building: sSOL is the delegated token, taking it for an AVS
+    /// mint would let sSOL -> sSOL look like a valid pair.
+    pub fn avs_overrides(mut self, avs_overrides: HashMap<Pubkey, Pubkey>) -> Self {
+        self.avs_overrides.extend(avs_overrides);
+        self
+    }
+
+    /// Mint of sSOL, `SOLAYER_SOL` by default, e.g. the sSOL mint on devnet
+    ///
+    /// Every direction check of the pool compares against it, the delegate side being sSOL.
+    pub fn ssol_mint(mut self, ssol_mint: Pubkey) -> Self {
+        self.ssol_mint = Some(ssol_mint);
+        self
+    }
+
+    /// Lets `quote` price AVS -> AVS as an undelegate to sSOL followed by a delegate, off by default
+    ///
+    /// Such a swap is two instructions, see `get_avs_to_avs_swap_legs`, so it takes twice the
+    /// accounts of a single delegate or undelegate.
+    pub fn avs_to_avs(mut self, enabled: bool) -> Self {
+        self.avs_to_avs = enabled;
+        self
+    }
+
+    /// Prepended as is to the label, e.g. `"MyDEX-"` gives `"MyDEX-Solayer (Bybit AVS)"`
+    pub fn label_prefix(mut self, label_prefix: impl Into<String>) -> Self {
+        self.label_prefix = label_prefix.into();
+        self
+    }
+
+    /// Program the swap account must be owned by, `SOLAYER_DELEGATION_PROGRAM` by default,
+    /// e.g. the delegation program deployed on devnet
+    pub fn program_id(mut self, program_id: Pubkey) -> Self {
+        self.program_id = Some(program_id);
+        self
+    }
+
+    /// Refuses quotes and swaps until the clock of the `AmmContext` reaches `activation_epoch`
+    pub fn activation_epoch(mut self, activation_epoch: u64) -> Self {
+        self.activation_epoch = Some(activation_epoch);
+        self
+    }
+
+    /// Smallest amount `quote` accepts, e.g. for an AVS with dust protection
+    pub fn min_amount(mut self, min_amount: u64) -> Self {
+        self.min_amount = Some(min_amount);
+        self
+    }
+
+    /// Largest amount `quote` accepts, e.g. for an AVS capping each delegation
+    pub fn max_amount(mut self, max_amount: u64) -> Self {
+        self.max_amount = Some(max_amount);
+        self
+    }
+
+    /// Slots undelegated sSOL stays locked for, the endoAVS account doesn't carry one today
+    pub fn undelegation_cooldown_slots(mut self, undelegation_cooldown_slots: u64) -> Self {
+        self.undelegation_cooldown_slots = Some(undelegation_cooldown_slots);
+        self
+    }
+
+    pub fn unknown_mint_policy(mut self, unknown_mint_policy: UnknownMintPolicy) -> Self {
+        self.unknown_mint_policy = unknown_mint_policy;
+        self
+    }
+
+    pub fn missing_ssol_policy(mut self, missing_ssol_policy: MissingSsolPolicy) -> Self {
+        self.missing_ssol_policy = missing_ssol_policy;
+        self
+    }
+
+    /// Mint -> token program known up front, e.g. an AVS mint under Token-2022, so swap metas
+    /// use it before `update` has seen the mint. The owner seen in `update` still wins.
+    pub fn token_programs(mut self, token_programs: HashMap<Pubkey, Pubkey>) -> Self {
+        self.token_programs.extend(token_programs);
+        self
+    }
+
+    /// Hypothetical AVS mint -> fee in basis points that `quote` takes on top of the pool's
+    /// trade fee, for what-if modeling, none by default
+    ///
+    /// Fails on an entry above `MAX_BPS`. The swap itself is unchanged, the delegation program
+    /// charges no such fee today.
+    pub fn avs_fee_bps(mut self, avs_fee_bps: HashMap<Pubkey, u16>) -> Result<Self, SolayerAmmError> {
+        if let Some((&avs_mint, &fee_bps)) = avs_fee_bps.iter().find(|(_, fee_bps)| **fee_bps > MAX_BPS) {
+            return Err(SolayerAmmError::InvalidAvsFeeBps { avs_mint, fee_bps });
+        }
+        self.avs_fee_bps.extend(avs_fee_bps);
+        Ok(self)
+    }
+
+    /// Quotes sSOL <-> AVS at `exchange_rate` instead of 1:1, see `SolayerEndoAVSAmm::set_exchange_rate`
+    ///
+    /// Fails on a zero numerator or denominator.
+    pub fn exchange_rate(mut self, exchange_rate: ExchangeRate) -> Result<Self, SolayerAmmError> {
+        exchange_rate.validate()?;
+        self.exchange_rate = Some(exchange_rate);
+        Ok(self)
+    }
+
+    pub fn build(self, keyed_account: &KeyedAccount, amm_context: &AmmContext) -> Result<SolayerEndoAVSAmm> {
+        self.build_from_account_data(
+            keyed_account.key,
+            keyed_account.account.owner,
+            &keyed_account.account.data,
+            amm_context,
+        )
+    }
+
+    /// Builds from the parts of a swap account, e.g. as received from a Geyser stream
+    pub fn build_from_account_data(
+        self,
+        key: Pubkey,
+        owner: Pubkey,
+        data: &[u8],
+        amm_context: &AmmContext,
+    ) -> Result<SolayerEndoAVSAmm> {
+        let expected_program_id = self.program_id.unwrap_or(SOLAYER_DELEGATION_PROGRAM);
+        if owner != expected_program_id {
+            return Err(SolayerAmmError::UnexpectedOwner {
+                account: key,
+                owner,
+                expected: expected_program_id,
+            }
+            .into());
+        }
+        // the key and length tell which account is bad, the error still downcasts to SolayerAmmError
+        let state = unpack_swap_state(&key, data)
+            .with_context(|| format!("failed to unpack swap account {key} ({} bytes)", data.len()))?;
+
+        // an endoAVS pool always delegates sSOL, a pool without it can't be routed
+        let ssol_mint = self.ssol_mint.unwrap_or(SOLAYER_SOL);
+        let reserve_mints = [state.token_a_mint(), state.token_b_mint()];
+        if !reserve_mints.contains(&ssol_mint) {
+            match self.missing_ssol_policy {
+                MissingSsolPolicy::Warn => {
+                    warn!(%key, ?reserve_mints, %ssol_mint, "swap account has sSOL on neither side")
+                }
+                MissingSsolPolicy::Error => {
+                    return Err(SolayerAmmError::MissingSsolReserve {
+                        account: key,
+                        ssol_mint,
+                    }
+                    .into())
+                }
+            }
+        }
+
+        Ok(self.build_with_state(key, state, owner, amm_context))
+    }
+
+    /// Builds from an already unpacked swap state
+    pub fn build_with_state(
+        self,
+        key: Pubkey,
+        state: impl Into<SwapState>,
+        program_id: Pubkey,
+        amm_context: &AmmContext,
+    ) -> SolayerEndoAVSAmm {
+        let state = state.into();
+        let reserve_mints = [state.token_a_mint(), state.token_b_mint()];
+
+        let ssol_mint = self.ssol_mint.unwrap_or(SOLAYER_SOL);
+        let mut avs_addresses = AVS_MINT_TO_ENDO_AVS_ADDRESS.clone();
+        avs_addresses.extend(self.avs_overrides);
+        avs_addresses.remove(&ssol_mint);
+        let endo_avs = reserve_mints.iter().find_map(|mint| avs_addresses.get(mint).copied());
+
+        // e.g. "Solayer (Bybit AVS)"
+        let avs_mint = reserve_mints[avs_mint_index(&reserve_mints, &ssol_mint)];
+        let prefix = &self.label_prefix;
+        let label = if avs_mint == ssol_mint {
+            format!("{prefix}Solayer")
+        } else {
+            format!("{prefix}Solayer ({})", avs_display_name(&avs_mint))
+        };
+
+        let mut amm = SolayerEndoAVSAmm {
+            key,
+            label,
+            state: Arc::new(state),
+            reserve_mints,
+            ssol_mint,
+            program_id,
+            reserves: Default::default(),
+            reserves_loaded: false,
+            avs_addresses,
+            endo_avs,
+            token_programs: self.token_programs,
+            destination_ata: None,
+            delegate_enabled: true,
+            undelegate_enabled: true,
+            avs_to_avs: self.avs_to_avs,
+            exchange_rate: self.exchange_rate,
+            mint_decimals: [None; 2],
+            // shared with the context so `activation_epoch` is checked against the live epoch
+            clock_ref: amm_context.clock_ref.clone(),
+            activation_epoch: self.activation_epoch,
+            min_amount: self.min_amount,
+            max_amount: self.max_amount,
+            undelegation_cooldown_slots: self.undelegation_cooldown_slots,
+            unknown_mint_policy: self.unknown_mint_policy,
+            avs_fee_bps: self.avs_fee_bps,
+            delegated_token_vault: None,
+        };
+        amm.refresh_delegated_token_vault();
+        amm
+    }
 }
 
+#[derive(Clone)]
 pub struct SolayerEndoAVSAmm {
     key: Pubkey,
     label: String,
-    state: SwapV1,
+    // SwapV1 doesn't implement Clone, share it instead
+    state: Arc<SwapState>,
     reserve_mints: [Pubkey; 2],
+    // the delegated side, `SOLAYER_SOL` unless the builder was given another one
+    ssol_mint: Pubkey,
     reserves: [u128; 2],
+    // whether update has loaded the reserves at least once
+    reserves_loaded: bool,
     program_id: Pubkey,
+    avs_addresses: HashMap<Pubkey, Pubkey>,
+    // endoAVS serving this pool's AVS mint, if it is a known one
+    endo_avs: Option<Pubkey>,
+    // mint -> owning token program, seeded by the builder and learnt in update
+    token_programs: HashMap<Pubkey, Pubkey>,
+    // destination ATA watched in update, with whether it existed at the last update
+    destination_ata: Option<(Pubkey, Option<bool>)>,
+    // e.g. undelegation is turned off while a cooldown is active
+    delegate_enabled: bool,
+    undelegate_enabled: bool,
+    // whether AVS -> AVS is quoted through sSOL
+    avs_to_avs: bool,
+    // 1:1 when unset, only ever set by the caller
+    exchange_rate: Option<ExchangeRate>,
+    // decimals of the reserve mints in storage order, read in update
+    mint_decimals: [Option<u8>; 2],
+    clock_ref: ClockRef,
+    // epoch from which delegation and undelegation are open, always open when unset
+    activation_epoch: Option<u64>,
+    // bounds on the quoted amount, unbounded when unset
+    min_amount: Option<u64>,
+    max_amount: Option<u64>,
+    undelegation_cooldown_slots: Option<u64>,
+    unknown_mint_policy: UnknownMintPolicy,
+    // AVS mint -> modeled fee taken by quote, see `SolayerEndoAVSAmmBuilder::avs_fee_bps`
+    avs_fee_bps: HashMap<Pubkey, u16>,
+    // sSOL vault of `endo_avs`, deriving it dominates the cost of building swap metas
+    delegated_token_vault: Option<Pubkey>,
+}
+
+// Kept compact, the full state and the maps would drown the pool itself
+impl fmt::Debug for SolayerEndoAVSAmm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SolayerEndoAVSAmm")
+            .field("key", &self.key)
+            .field("label", &self.label)
+            .field("reserve_mints", &self.reserve_mints)
+            .field("reserves", &self.reserves)
+            .finish_non_exhaustive()
+    }
+}
+
+/// `<label> <key>: <reserve> <mint> / <reserve> <mint>`, in storage order
+impl fmt::Display for SolayerEndoAVSAmm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {} {} / {} {}",
+            self.label, self.key, self.reserves[0], self.reserve_mints[0], self.reserves[1], self.reserve_mints[1]
+        )
+    }
+}
+
+/// Serializable copy of a `SolayerEndoAVSAmm`, to restore it without fetching accounts again
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SolayerEndoAVSAmmSnapshot {
+    pub key: Pubkey,
+    pub label: String,
+    pub program_id: Pubkey,
+    pub reserve_mints: [Pubkey; 2],
+    /// Absent from snapshots taken before it was configurable, those are mainnet sSOL
+    #[serde(default = "mainnet_ssol_mint")]
+    pub ssol_mint: Pubkey,
+    pub reserves: [u128; 2],
+    pub reserves_loaded: bool,
+    /// Layout version of `state`, snapshots taken before it was recorded are all SwapV1
+    #[serde(default = "swap_v1_version")]
+    pub state_version: u8,
+    /// Swap state in its packed on-chain layout, without the version byte
+    pub state: Vec<u8>,
+    /// Pairs rather than maps, JSON only takes string keys
+    pub avs_addresses: Vec<(Pubkey, Pubkey)>,
+    pub token_programs: Vec<(Pubkey, Pubkey)>,
+    #[serde(default)]
+    pub exchange_rate: Option<ExchangeRate>,
+    /// Decimals of `reserve_mints`, `None` until an update has read the mint
+    #[serde(default)]
+    pub mint_decimals: [Option<u8>; 2],
+    #[serde(default = "enabled")]
+    pub delegate_enabled: bool,
+    #[serde(default = "enabled")]
+    pub undelegate_enabled: bool,
+    #[serde(default)]
+    pub avs_to_avs: bool,
+    #[serde(default)]
+    pub activation_epoch: Option<u64>,
+    #[serde(default)]
+    pub min_amount: Option<u64>,
+    #[serde(default)]
+    pub max_amount: Option<u64>,
+    #[serde(default)]
+    pub undelegation_cooldown_slots: Option<u64>,
+    #[serde(default)]
+    pub unknown_mint_policy: UnknownMintPolicy,
+    #[serde(default)]
+    pub avs_fee_bps: Vec<(Pubkey, u16)>,
+}
+
+#[cfg(feature = "serde")]
+fn mainnet_ssol_mint() -> Pubkey {
+    SOLAYER_SOL
 }
 
-impl Clone for SolayerEndoAVSAmm {
-    fn clone(&self) -> Self {
-        SolayerEndoAVSAmm {
+#[cfg(feature = "serde")]
+fn swap_v1_version() -> u8 {
+    SWAP_V1_VERSION
+}
+
+#[cfg(feature = "serde")]
+fn enabled() -> bool {
+    true
+}
+
+#[cfg(feature = "serde")]
+impl SolayerEndoAVSAmm {
+    pub fn to_snapshot(&self) -> SolayerEndoAVSAmmSnapshot {
+        // without the version byte, kept apart in `state_version`
+        let state = self.state.pack()[1..].to_vec();
+
+        SolayerEndoAVSAmmSnapshot {
             key: self.key,
             label: self.label.clone(),
-            state: SwapV1 {
-                is_initialized: self.state.is_initialized,
-                bump_seed: self.state.bump_seed,
-                token_program_id: self.state.token_program_id,
-                token_a: self.state.token_a,
-                token_b: self.state.token_b,
-                pool_mint: self.state.pool_mint,
-                token_a_mint: self.state.token_a_mint,
-                token_b_mint: self.state.token_b_mint,
-                pool_fee_account: self.state.pool_fee_account,
-                fees: self.state.fees.clone(),
-                swap_curve: SwapCurve {
-                    curve_type: self.state.swap_curve.curve_type,
-                    calculator: self.state.swap_curve.calculator.clone(),
-                },
-            },
-            reserve_mints: self.reserve_mints,
             program_id: self.program_id,
+            reserve_mints: self.reserve_mints,
+            ssol_mint: self.ssol_mint,
             reserves: self.reserves,
+            reserves_loaded: self.reserves_loaded,
+            state_version: self.state.version(),
+            state,
+            avs_addresses: self.avs_addresses.iter().map(|(mint, address)| (*mint, *address)).collect(),
+            token_programs: self.token_programs.iter().map(|(mint, program)| (*mint, *program)).collect(),
+            exchange_rate: self.exchange_rate,
+            mint_decimals: self.mint_decimals,
+            delegate_enabled: self.delegate_enabled,
+            undelegate_enabled: self.undelegate_enabled,
+            avs_to_avs: self.avs_to_avs,
+            activation_epoch: self.activation_epoch,
+            min_amount: self.min_amount,
+            max_amount: self.max_amount,
+            undelegation_cooldown_slots: self.undelegation_cooldown_slots,
+            unknown_mint_policy: self.unknown_mint_policy,
+            avs_fee_bps: self.avs_fee_bps.iter().map(|(mint, fee_bps)| (*mint, *fee_bps)).collect(),
         }
     }
+
+    pub fn from_snapshot(snapshot: SolayerEndoAVSAmmSnapshot, amm_context: &AmmContext) -> Result<Self> {
+        let account_data = [&[snapshot.state_version][..], &snapshot.state].concat();
+        let state = unpack_swap_state(&snapshot.key, &account_data)?;
+        let avs_addresses: HashMap<Pubkey, Pubkey> = snapshot.avs_addresses.into_iter().collect();
+        let endo_avs = snapshot
+            .reserve_mints
+            .iter()
+            .find_map(|mint| avs_addresses.get(mint).copied());
+
+        let mut amm = Self {
+            key: snapshot.key,
+            label: snapshot.label,
+            state: Arc::new(state),
+            reserve_mints: snapshot.reserve_mints,
+            ssol_mint: snapshot.ssol_mint,
+            reserves: snapshot.reserves,
+            reserves_loaded: snapshot.reserves_loaded,
+            program_id: snapshot.program_id,
+            avs_addresses,
+            endo_avs,
+            token_programs: snapshot.token_programs.into_iter().collect(),
+            destination_ata: None,
+            delegate_enabled: snapshot.delegate_enabled,
+            undelegate_enabled: snapshot.undelegate_enabled,
+            avs_to_avs: snapshot.avs_to_avs,
+            exchange_rate: snapshot.exchange_rate,
+            mint_decimals: snapshot.mint_decimals,
+            clock_ref: amm_context.clock_ref.clone(),
+            activation_epoch: snapshot.activation_epoch,
+            min_amount: snapshot.min_amount,
+            max_amount: snapshot.max_amount,
+            undelegation_cooldown_slots: snapshot.undelegation_cooldown_slots,
+            unknown_mint_policy: snapshot.unknown_mint_policy,
+            avs_fee_bps: snapshot.avs_fee_bps.into_iter().collect(),
+            delegated_token_vault: None,
+        };
+        amm.refresh_delegated_token_vault();
+        Ok(amm)
+    }
 }
 
-impl Amm for SolayerEndoAVSAmm {
-    fn from_keyed_account(keyed_account: &KeyedAccount, _amm_context: &AmmContext) -> Result<Self> {
-        // Skip the first byte which is version
-        let state = SwapV1::unpack(&keyed_account.account.data[1..])?;
-        let reserve_mints = [
-            Pubkey::from(state.token_a_mint.to_bytes()),
-            Pubkey::from(state.token_b_mint.to_bytes()),
-        ];
+#[cfg(feature = "rpc")]
+impl SolayerEndoAVSAmm {
+    /// Fetches the swap account at `key` and builds the AMM from it
+    pub async fn fetch(client: &RpcClient, key: Pubkey, amm_context: &AmmContext) -> Result<Self> {
+        let account = client.get_account(&key).await?;
+        let keyed_account = KeyedAccount {
+            key,
+            account,
+            params: None,
+        };
+        Self::from_keyed_account(&keyed_account, amm_context)
+    }
+}
 
-        Ok(Self {
-            key: keyed_account.key,
-            label: "Solayer".into(),
-            state,
-            reserve_mints,
-            program_id: keyed_account.account.owner,
-            reserves: Default::default(),
-        })
+impl SolayerEndoAVSAmm {
+    /// Creates the AMM from raw swap account data with the built-in AVS set, without a `KeyedAccount`
+    pub fn from_account_data(key: Pubkey, owner: Pubkey, data: &[u8], amm_context: &AmmContext) -> Result<Self> {
+        SolayerEndoAVSAmmBuilder::new().build_from_account_data(key, owner, data, amm_context)
     }
 
-    fn label(&self) -> String {
-        self.label.clone()
+    /// Creates the AMM from an already unpacked swap state with the built-in AVS set
+    pub fn new(key: Pubkey, state: impl Into<SwapState>, program_id: Pubkey, amm_context: &AmmContext) -> Self {
+        SolayerEndoAVSAmmBuilder::new().build_with_state(key, state, program_id, amm_context)
     }
 
-    fn program_id(&self) -> Pubkey {
-        self.program_id
+    /// Whether `mint` is one of the built-in AVS mints
+    pub fn is_supported_avs_mint(mint: &Pubkey) -> bool {
+        AVS_MINT_TO_ENDO_AVS_ADDRESS.contains_key(mint)
     }
 
-    fn key(&self) -> Pubkey {
-        self.key
+    /// Every built-in AVS with its mint, endoAVS address and name
+    pub fn supported_avs() -> impl Iterator<Item = SupportedAvs> {
+        built_in_avs().iter().copied()
     }
 
-    fn get_reserve_mints(&self) -> Vec<Pubkey> {
-        self.reserve_mints.to_vec()
+    /// The built-in AVS mint named `name`, ignoring case, e.g. "bybit" or "Kamino Finance"
+    pub fn from_avs_name(name: &str) -> Option<Pubkey> {
+        built_in_avs()
+            .iter()
+            .find(|avs| avs.name.eq_ignore_ascii_case(name))
+            .map(|avs| avs.mint)
     }
 
-    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
-        vec![
-            Pubkey::from(self.state.token_a.to_bytes()),
-            Pubkey::from(self.state.token_b.to_bytes()),
-        ]
+    /// All built-in AVS mints, always in the order of `SUPPORTED_AVS`
+    pub fn supported_avs_mints() -> Vec<Pubkey> {
+        built_in_avs().iter().map(|avs| avs.mint).collect()
     }
 
-    fn update(&mut self, _account_map: &AccountMap) -> Result<()> {
-        // no action is needed here
-        Ok(())
+    /// The built-in AVS mint served by the endoAVS at `endo_avs_address`
+    pub fn avs_mint_for_endo_avs(endo_avs_address: &Pubkey) -> Option<Pubkey> {
+        ENDO_AVS_ADDRESS_TO_AVS_MINT.get(endo_avs_address).copied()
     }
 
-    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
-        // endoAVS token to sSOL would always be 1:1, no fee
-        Ok(Quote {
-            in_amount: quote_params.amount,
-            out_amount: quote_params.amount,
-            fee_amount: 0,
-            fee_mint: quote_params.input_mint,
-            ..Quote::default()
-        })
+    /// The unpacked swap state of the pool
+    pub fn state(&self) -> &SwapState {
+        &self.state
     }
 
-    /// Indicates which Swap has to be performed along with all the necessary account metas
-    fn get_swap_and_account_metas(&self, swap_params: &SwapParams) -> Result<SwapAndAccountMetas> {
+    /// Account collecting the pool's fees
+    pub fn pool_fee_account(&self) -> Pubkey {
+        self.state.pool_fee_account()
+    }
 
-        if swap_params.source_mint == SOLAYER_SOL {
-            // delegate sSOL to endoAVS
-            let endo_avs: Pubkey = *AVS_MINT_TO_ENDO_AVS_ADDRESS.get(&swap_params.destination_mint).unwrap();
-            let delegated_token_vault: Pubkey = get_associated_token_address(&endo_avs, &swap_params.source_mint);
-            Ok(SwapAndAccountMetas {
-                // TODO: this should be Swap::Solayer
-                swap: Swap::TokenSwap,
-                account_metas: vec![
-                    // staker
-                    AccountMeta::new_readonly(swap_params.token_transfer_authority, true),
-                    // endoAvs
-                    AccountMeta::new_readonly(endo_avs, false),
-                    // avsTokenMint
-                    AccountMeta::new(swap_params.destination_mint, false),
-                    // delegatedTokenVault
-                    AccountMeta::new(delegated_token_vault, false),
-                    // delegatedTokenMint
-                    AccountMeta::new_readonly(swap_params.source_mint, false),
-                    // stakerDelegatedTokenAccount
-                    AccountMeta::new(swap_params.source_token_account, false),
-                    // stakerAvsTokenAccount
-                    AccountMeta::new(swap_params.destination_token_account, false),
-                    // tokenProgram
-                    AccountMeta::new_readonly(spl_token::id(), false),
-                ],
-            })
+    /// Token balances of the pool, in the same order as `get_reserve_mints`
+    pub fn reserves(&self) -> [u128; 2] {
+        self.reserves
+    }
+
+    /// The sSOL side of the pool, whatever its storage order
+    pub fn sol_mint(&self) -> Pubkey {
+        self.reserve_mints[1 - avs_mint_index(&self.reserve_mints, &self.ssol_mint)]
+    }
+
+    /// The AVS token side of the pool, whatever its storage order
+    pub fn avs_mint(&self) -> Pubkey {
+        self.reserve_mints[avs_mint_index(&self.reserve_mints, &self.ssol_mint)]
+    }
+
+    /// Slot at which sSOL undelegated at `current_slot` unlocks, `None` without a cooldown
+    pub fn undelegation_available_slot(&self, current_slot: u64) -> Option<u64> {
+        self.undelegation_cooldown_slots
+            .map(|cooldown_slots| current_slot.saturating_add(cooldown_slots))
+    }
+
+    /// Changes from `self` to `other`, typically the same pool polled later
+    pub fn diff(&self, other: &SolayerEndoAVSAmm) -> AmmDiff {
+        fn changed<T: PartialEq>(before: T, after: T) -> Option<(T, T)> {
+            (before != after).then_some((before, after))
+        }
+        let trade_fee = |amm: &SolayerEndoAVSAmm| {
+            let fees = amm.state.fees();
+            (fees.trade_fee_numerator, fees.trade_fee_denominator)
+        };
+
+        AmmDiff {
+            reserves: changed(self.reserves, other.reserves),
+            exchange_rate: changed(self.exchange_rate, other.exchange_rate),
+            trade_fee: changed(trade_fee(self), trade_fee(other)),
+        }
+    }
+
+    /// Cheap liquidity check, `Unknown` until `update` has loaded the reserves
+    ///
+    /// Quotes don't need it, they are served 1:1 before any update, but routers can use it to
+    /// prune pools once accounts are fetched.
+    pub fn liquidity_state(&self) -> LiquidityState {
+        if !self.reserves_loaded {
+            LiquidityState::Unknown
+        } else if self.reserves.iter().all(|reserve| *reserve == 0) {
+            LiquidityState::Empty
         } else {
-            // undelegate endoAVS to sSOL
-            let endo_avs: Pubkey = *AVS_MINT_TO_ENDO_AVS_ADDRESS.get(&swap_params.source_mint).unwrap();
-            let delegated_token_vault: Pubkey = get_associated_token_address(&endo_avs, &swap_params.destination_mint);
-            Ok(SwapAndAccountMetas {
-                // TODO: this should be Swap::Solayer
-                swap: Swap::TokenSwap,
-                account_metas: vec![
-                    // staker
-                    AccountMeta::new_readonly(swap_params.token_transfer_authority, true),
-                    // endoAvs
-                    AccountMeta::new_readonly(*AVS_MINT_TO_ENDO_AVS_ADDRESS.get(&swap_params.source_mint).unwrap(), false),
-                    // avsTokenMint
-                    AccountMeta::new(swap_params.source_mint, false),
-                    // delegatedTokenVault
-                    AccountMeta::new(delegated_token_vault, false),
-                    // delegatedTokenMint
-                    AccountMeta::new_readonly(swap_params.destination_mint, false),
-                    // stakerDelegatedTokenAccount
-                    AccountMeta::new(swap_params.destination_token_account, false),
-                    // stakerAvsTokenAccount
-                    AccountMeta::new(swap_params.source_token_account, false),
-                    // tokenProgram
-                    AccountMeta::new_readonly(spl_token::id(), false),
-                ],
-            })
+            LiquidityState::Available
         }
     }
 
-    // Indicates that whether ExactOut mode is supported
-    fn supports_exact_out(&self) -> bool {
-        true
+    /// Directed (input, output) pairs this pool routes: sSOL -> AVS and AVS -> sSOL, less any
+    /// direction turned off with `set_direction_enabled`
+    pub fn routable_pairs(&self) -> Vec<(Pubkey, Pubkey)> {
+        let (sol_mint, avs_mint) = (self.sol_mint(), self.avs_mint());
+        [
+            (SwapDirection::Delegate, (sol_mint, avs_mint)),
+            (SwapDirection::Undelegate, (avs_mint, sol_mint)),
+        ]
+        .into_iter()
+        .filter(|(direction, _)| self.is_direction_enabled(*direction))
+        .map(|(_, pair)| pair)
+        .collect()
     }
 
-    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
-        Box::new(self.clone())
+    /// `[sSOL, AVS mint]` whatever the storage order, unlike `get_reserve_mints` which keeps it
+    pub fn normalized_reserve_mints(&self) -> [Pubkey; 2] {
+        [self.sol_mint(), self.avs_mint()]
     }
 
-    // TODO: shorten this len
-    fn get_accounts_len(&self) -> usize {
-        32 // Default to a near whole legacy transaction to penalize no implementation
+    /// `reserves` in the order of `normalized_reserve_mints`
+    pub fn normalized_reserves(&self) -> [u128; 2] {
+        let avs_index = avs_mint_index(&self.reserve_mints, &self.ssol_mint);
+        [self.reserves[1 - avs_index], self.reserves[avs_index]]
+    }
+
+    /// Checks that the swap's token accounts hold the declared mints, skipping accounts absent from `account_map`
+    pub fn validate_swap_token_accounts(
+        swap_params: &SwapParams,
+        account_map: &AccountMap,
+    ) -> Result<(), SolayerAmmError> {
+        let token_accounts = [
+            ("source", swap_params.source_token_account, swap_params.source_mint),
+            ("destination", swap_params.destination_token_account, swap_params.destination_mint),
+        ];
+        for (side, address, expected_mint) in token_accounts {
+            if let Some(account) = account_map.get(&address) {
+                let mint = unpack_token_account(&address, &account.data)?.mint;
+                if mint != expected_mint {
+                    return Err(SolayerAmmError::TokenAccountMintMismatch {
+                        side,
+                        address,
+                        mint,
+                        expected_mint,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `token_transfer_authority` can move `in_amount` out of the source token account,
+    /// skipping the check when the account is absent from `account_map`
+    ///
+    /// The staker meta is the transfer authority, which need not own the token accounts: a
+    /// program signing for a user works as long as the user approved it as delegate of the
+    /// source account for at least `in_amount`. The destination only has to be credited, so its
+    /// owner is free.
+    pub fn validate_transfer_authority(
+        swap_params: &SwapParams,
+        account_map: &AccountMap,
+    ) -> Result<(), SolayerAmmError> {
+        let address = swap_params.source_token_account;
+        let Some(account) = account_map.get(&address) else {
+            return Ok(());
+        };
+        let token_account = unpack_token_account(&address, &account.data)?;
+        let authority = swap_params.token_transfer_authority;
+        let is_delegate = token_account.delegate == COption::Some(authority)
+            && token_account.delegated_amount >= swap_params.in_amount;
+        if token_account.owner != authority && !is_delegate {
+            return Err(SolayerAmmError::UnauthorizedTransferAuthority {
+                authority,
+                token_account: address,
+                owner: token_account.owner,
+            });
+        }
+        Ok(())
+    }
+
+    /// Associated token account of `owner` for `destination_mint`, under the mint's token program
+    pub fn expected_destination_ata(&self, owner: &Pubkey, destination_mint: &Pubkey) -> Pubkey {
+        get_associated_token_address_with_program_id(owner, destination_mint, &self.token_program(destination_mint))
+    }
+
+    /// Idempotently creates `owner`'s destination ATA, derived and owned under the mint's token
+    /// program so a Token-2022 destination gets a Token-2022 account
+    pub fn create_destination_ata_instruction(
+        &self,
+        payer: &Pubkey,
+        owner: &Pubkey,
+        destination_mint: &Pubkey,
+    ) -> Instruction {
+        create_associated_token_account_idempotent(payer, owner, destination_mint, &self.token_program(destination_mint))
+    }
+
+    /// Whether the swap's destination token account is missing from `account_map` (or empty) and
+    /// so has to be created first, adding `ATA_CREATION_ACCOUNTS_LEN` accounts and its rent
+    pub fn needs_destination_ata_creation(&self, swap_params: &SwapParams, account_map: &AccountMap) -> bool {
+        account_map
+            .get(&swap_params.destination_token_account)
+            .is_none_or(|account| account.data.is_empty())
+    }
+
+    /// Watches `ata` in `update` so `get_accounts_len` can tell whether the swap must create it
+    pub fn watch_destination_ata(&mut self, ata: Pubkey) {
+        self.destination_ata = Some((ata, None));
+    }
+
+    // Whether the swap's destination is the watched ATA and it was missing at the last update
+    fn destination_ata_missing(&self, swap_params: &SwapParams) -> bool {
+        self.destination_ata == Some((swap_params.destination_token_account, Some(false)))
+    }
+
+    // Rent-exempt balance of an ATA for `mint`, which Token-2022 creates with ImmutableOwner
+    fn ata_rent_lamports(&self, mint: &Pubkey, rent: &Rent) -> u64 {
+        let len = if self.token_program(mint) == spl_token_2022::id() {
+            ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&[
+                ExtensionType::ImmutableOwner,
+            ])
+            .unwrap_or(TokenAccount::LEN)
+        } else {
+            TokenAccount::LEN
+        };
+        rent.minimum_balance(len)
+    }
+
+    /// `swap_params.out_amount` less the rent of creating the destination ATA, for showing the
+    /// true cost of the swap
+    ///
+    /// Only the ATA watched with `watch_destination_ata` and missing at the last update counts
+    /// as created. Both sides are treated as worth one lamport per base unit, as sSOL and the
+    /// AVS tokens are delegated 1:1. Informational, the swap itself is unchanged.
+    pub fn net_output_after_rent(&self, swap_params: &SwapParams, rent: &Rent) -> u64 {
+        swap_params
+            .out_amount
+            .saturating_sub(self.required_prefund_lamports(swap_params, rent))
+    }
+
+    /// Lamports the payer needs up front for the swap: the rent of the destination ATA when it
+    /// has to be created, zero otherwise
+    ///
+    /// Like `net_output_after_rent`, only the ATA watched with `watch_destination_ata` and
+    /// missing at the last update is created.
+    pub fn required_prefund_lamports(&self, swap_params: &SwapParams, rent: &Rent) -> u64 {
+        if self.destination_ata_missing(swap_params) {
+            self.ata_rent_lamports(&swap_params.destination_mint, rent)
+        } else {
+            0
+        }
+    }
+
+    /// Whether `quote` still depends on accounts `update` hasn't read yet
+    ///
+    /// True until an update has seen both reserves and both mints: before that quotes are 1:1 on
+    /// base units, unscaled for mints of differing decimals and served even out of an empty reserve.
+    pub fn requires_update_for_quote(&self) -> bool {
+        !self.reserves_loaded || self.mint_decimals.contains(&None)
+    }
+
+    /// Turns one direction of the pool on or off, both are on by default
+    pub fn set_direction_enabled(&mut self, direction: SwapDirection, enabled: bool) {
+        match direction {
+            SwapDirection::Delegate => self.delegate_enabled = enabled,
+            SwapDirection::Undelegate => self.undelegate_enabled = enabled,
+        }
+    }
+
+    pub fn is_direction_enabled(&self, direction: SwapDirection) -> bool {
+        match direction {
+            SwapDirection::Delegate => self.delegate_enabled,
+            SwapDirection::Undelegate => self.undelegate_enabled,
+        }
+    }
+
+    /// Quotes sSOL <-> AVS at `exchange_rate` instead of 1:1, `None` restores 1:1
+    ///
+    /// The endoAVS account carries no rate today and the program delegates 1:1, so `update`
+    /// never touches it: callers of AVS whose token drifts from 1:1 set it here or on the
+    /// builder. AVS -> AVS quotes stay 1:1.
+    pub fn set_exchange_rate(&mut self, exchange_rate: Option<ExchangeRate>) -> Result<(), SolayerAmmError> {
+        if let Some(rate) = &exchange_rate {
+            rate.validate()?;
+        }
+        self.exchange_rate = exchange_rate;
+        Ok(())
+    }
+
+    pub fn exchange_rate(&self) -> Option<ExchangeRate> {
+        self.exchange_rate
+    }
+
+    /// Whether `quote` can serve `input_mint` -> `output_mint`: a valid pair whose direction is enabled
+    pub fn can_quote(&self, input_mint: &Pubkey, output_mint: &Pubkey) -> bool {
+        if !self.is_active() {
+            return false;
+        }
+        if self.is_avs_to_avs(input_mint, output_mint) {
+            return self.delegate_enabled && self.undelegate_enabled;
+        }
+        self.validate_mint_pair(input_mint, output_mint).is_ok()
+            && self.is_direction_enabled(SwapDirection::from_source_mint(input_mint, &self.ssol_mint))
+    }
+
+    // Fails before the activation epoch, if the pool has one
+    fn ensure_active(&self) -> Result<(), SolayerAmmError> {
+        if let Some(activation_epoch) = self.activation_epoch {
+            let epoch = self.clock_ref.epoch.load(Ordering::Relaxed);
+            if epoch < activation_epoch {
+                return Err(SolayerAmmError::NotYetActive { epoch, activation_epoch });
+            }
+        }
+        Ok(())
+    }
+
+    // (input, output) decimals once update has read both mints
+    fn quote_decimals(&self, input_mint: &Pubkey) -> Option<(u8, u8)> {
+        let input_index = if *input_mint == self.reserve_mints[0] { 0 } else { 1 };
+        Some((self.mint_decimals[input_index]?, self.mint_decimals[1 - input_index]?))
+    }
+
+    // Fails when `amount` is outside the bounds set on the builder
+    fn ensure_amount_in_range(&self, amount: u64) -> Result<(), SolayerAmmError> {
+        if let Some(min_amount) = self.min_amount.filter(|min_amount| amount < *min_amount) {
+            return Err(SolayerAmmError::AmountBelowMinimum { amount, min_amount });
+        }
+        if let Some(max_amount) = self.max_amount.filter(|max_amount| amount > *max_amount) {
+            return Err(SolayerAmmError::AmountAboveMaximum { amount, max_amount });
+        }
+        Ok(())
+    }
+
+    // Two distinct known AVS mints, quoted through sSOL when the builder enabled it
+    fn is_avs_to_avs(&self, input_mint: &Pubkey, output_mint: &Pubkey) -> bool {
+        self.avs_to_avs
+            && input_mint != output_mint
+            && self.avs_addresses.contains_key(input_mint)
+            && self.avs_addresses.contains_key(output_mint)
+    }
+
+    // Undelegate then delegate, each leg 1:1 less the trade fee and the modeled fee of its AVS
+    fn quote_avs_to_avs(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        for direction in [SwapDirection::Undelegate, SwapDirection::Delegate] {
+            if !self.is_direction_enabled(direction) {
+                return Err(SolayerAmmError::DirectionDisabled(direction).into());
+            }
+        }
+        if quote_params.amount == 0 {
+            return Err(SolayerAmmError::ZeroAmount.into());
+        }
+
+        let (in_amount, out_amount) = match quote_params.swap_mode {
+            SwapMode::ExactIn => {
+                let sol_amount = self.amount_after_fees(quote_params.amount, &quote_params.input_mint)?;
+                (quote_params.amount, self.amount_after_fees(sol_amount, &quote_params.output_mint)?)
+            }
+            SwapMode::ExactOut => {
+                let sol_amount = self.amount_before_fees(quote_params.amount, &quote_params.output_mint)?;
+                (self.amount_before_fees(sol_amount, &quote_params.input_mint)?, quote_params.amount)
+            }
+        };
+        let fee_amount = in_amount
+            .checked_sub(out_amount)
+            .ok_or(SolayerAmmError::MathOverflow("fee amount"))?;
+        Ok(Quote {
+            min_in_amount: Some(in_amount),
+            min_out_amount: Some(out_amount),
+            in_amount,
+            out_amount,
+            fee_amount,
+            fee_mint: self.fee_mint(),
+            fee_pct: Decimal::from(fee_amount) / Decimal::from(in_amount),
+        })
+    }
+
+    /// `quote` failing when the input it requires exceeds `max_in_amount`
+    ///
+    /// Meant for ExactOut, where fees and the exchange rate decide the input, so a caller never
+    /// delegates more than it meant to. An ExactIn quote never exceeds a cap at least its amount.
+    pub fn quote_with_max_in(&self, quote_params: &QuoteParams, max_in_amount: u64) -> Result<Quote> {
+        let quote = self.quote(quote_params)?;
+        if quote.in_amount > max_in_amount {
+            return Err(SolayerAmmError::MaxInAmountExceeded {
+                in_amount: quote.in_amount,
+                max_in_amount,
+            }
+            .into());
+        }
+        Ok(quote)
+    }
+
+    /// `quote` for a caller holding the endoAVS address rather than the AVS mint
+    ///
+    /// `pool_address` is resolved to its AVS mint, which must be one side of `quote_params`.
+    pub fn quote_for_pool(&self, pool_address: &Pubkey, quote_params: &QuoteParams) -> Result<Quote> {
+        let avs_mint = self
+            .avs_addresses
+            .iter()
+            .find_map(|(avs_mint, address)| (address == pool_address).then_some(*avs_mint))
+            .ok_or(SolayerAmmError::UnknownEndoAvs(*pool_address))?;
+        if quote_params.input_mint != avs_mint && quote_params.output_mint != avs_mint {
+            return Err(SolayerAmmError::EndoAvsMintMismatch {
+                endo_avs: *pool_address,
+                avs_mint,
+            }
+            .into());
+        }
+        self.quote(quote_params)
+    }
+
+    /// `quote` along with the endoAVS it goes through, which `Quote` has no field for
+    ///
+    /// For AVS -> AVS this is the endoAVS of the input mint, undelegated from first.
+    pub fn quote_with_pool(&self, quote_params: &QuoteParams) -> Result<(Quote, Pubkey)> {
+        let quote = self.quote(quote_params)?;
+        let avs_mint = if quote_params.input_mint == self.ssol_mint {
+            quote_params.output_mint
+        } else {
+            quote_params.input_mint
+        };
+        Ok((quote, self.endo_avs_address(&avs_mint)?))
+    }
+
+    /// Quotes and builds the matching swap metas from one borrow of the pool
+    ///
+    /// `update` can't run in between, so unlike separate `quote` and `get_swap_and_account_metas`
+    /// calls the metas always match the quote. The mints, mode and amounts of
+    /// `swap_params` are taken from `quote_params` and the quote.
+    pub fn quote_and_swap_metas(
+        &self,
+        quote_params: &QuoteParams,
+        swap_params: &SwapParams,
+    ) -> Result<(Quote, SwapAndAccountMetas)> {
+        let quote = self.quote(quote_params)?;
+        let swap_params = SwapParams {
+            swap_mode: quote_params.swap_mode,
+            in_amount: quote.in_amount,
+            out_amount: quote.out_amount,
+            source_mint: quote_params.input_mint,
+            destination_mint: quote_params.output_mint,
+            ..*swap_params
+        };
+        let swap_and_account_metas = self.get_swap_and_account_metas(&swap_params)?;
+        Ok((quote, swap_and_account_metas))
+    }
+
+    /// User-facing summary of the quote, e.g. "Delegate to Bybit AVS" or "Undelegate from OKX AVS"
+    pub fn quote_action_label(&self, quote_params: &QuoteParams) -> String {
+        let (input_mint, output_mint) = (&quote_params.input_mint, &quote_params.output_mint);
+        if self.is_avs_to_avs(input_mint, output_mint) {
+            format!("Swap {} to {}", avs_display_name(input_mint), avs_display_name(output_mint))
+        } else if *input_mint == self.ssol_mint {
+            format!("Delegate to {}", avs_display_name(output_mint))
+        } else {
+            format!("Undelegate from {}", avs_display_name(input_mint))
+        }
+    }
+
+    /// The undelegate and delegate swaps making up an AVS -> AVS swap quoted with `avs_to_avs`
+    ///
+    /// The sSOL in between goes through the staker's sSOL associated token account, which
+    /// must already exist. The two legs carry `2 * SWAP_ACCOUNTS_LEN` accounts in total.
+    pub fn get_avs_to_avs_swap_legs(&self, swap_params: &SwapParams) -> Result<[SwapAndAccountMetas; 2]> {
+        if !self.is_avs_to_avs(&swap_params.source_mint, &swap_params.destination_mint) {
+            return Err(SolayerAmmError::UnsupportedMintPair {
+                input_mint: swap_params.source_mint,
+                output_mint: swap_params.destination_mint,
+            }
+            .into());
+        }
+        let sol_token_account = get_associated_token_address_with_program_id(
+            &swap_params.token_transfer_authority,
+            &self.ssol_mint,
+            &self.token_program(&self.ssol_mint),
+        );
+        let undelegate = SwapParams {
+            destination_mint: self.ssol_mint,
+            destination_token_account: sol_token_account,
+            ..*swap_params
+        };
+        let delegate = SwapParams {
+            source_mint: self.ssol_mint,
+            source_token_account: sol_token_account,
+            ..*swap_params
+        };
+        let undelegate = SolayerSwapRequest::from_avs_to_avs_leg(self, &undelegate)?;
+        let delegate = SolayerSwapRequest::from_avs_to_avs_leg(self, &delegate)?;
+        Ok([undelegate, delegate].map(|request| SwapAndAccountMetas {
+            swap: request.direction.swap(),
+            account_metas: self.build_account_metas(&request).to_vec(),
+        }))
+    }
+
+    // Fails when the direction starting from `input_mint` is turned off
+    fn ensure_direction_enabled(&self, input_mint: &Pubkey) -> Result<(), SolayerAmmError> {
+        let direction = SwapDirection::from_source_mint(input_mint, &self.ssol_mint);
+        if !self.is_direction_enabled(direction) {
+            return Err(SolayerAmmError::DirectionDisabled(direction));
+        }
+        Ok(())
+    }
+
+    /// The delegate or undelegate instruction for this swap, ready to be sent
+    ///
+    /// Same accounts as `get_swap_and_account_metas`, with `swap_params.in_amount` encoded after
+    /// the instruction discriminator.
+    pub fn build_instruction(&self, swap_params: &SwapParams) -> Result<Instruction> {
+        let direction = SwapDirection::from_source_mint(&swap_params.source_mint, &self.ssol_mint);
+        let SwapAndAccountMetas { account_metas, .. } = self.get_swap_and_account_metas(swap_params)?;
+        Ok(Instruction {
+            program_id: self.program_id,
+            accounts: account_metas,
+            data: direction.instruction_data(swap_params.in_amount),
+        })
+    }
+
+    /// Every account a local simulation of the swap needs: the metas, both mints, the
+    /// delegation program and the token program of each mint, without duplicates
+    pub fn accounts_for_simulation(&self, swap_params: &SwapParams) -> Result<Vec<Pubkey>> {
+        let SwapAndAccountMetas { account_metas, .. } = self.get_swap_and_account_metas(swap_params)?;
+        let mut accounts: Vec<Pubkey> = account_metas.iter().map(|account_meta| account_meta.pubkey).collect();
+        accounts.extend(self.reserve_mints);
+        accounts.push(self.program_id);
+        accounts.extend(self.reserve_mints.map(|mint| self.token_program(&mint)));
+
+        let mut seen = HashSet::new();
+        accounts.retain(|account| seen.insert(*account));
+        Ok(accounts)
+    }
+
+    /// Accounts the swap write-locks, for detecting lock conflicts across parallel routes
+    pub fn writable_accounts(&self, swap_params: &SwapParams) -> Result<Vec<Pubkey>> {
+        self.accounts_by_writability(swap_params, true)
+    }
+
+    /// Accounts the swap only reads
+    pub fn readonly_accounts(&self, swap_params: &SwapParams) -> Result<Vec<Pubkey>> {
+        self.accounts_by_writability(swap_params, false)
+    }
+
+    fn accounts_by_writability(&self, swap_params: &SwapParams, is_writable: bool) -> Result<Vec<Pubkey>> {
+        let SwapAndAccountMetas { account_metas, .. } = self.get_swap_and_account_metas(swap_params)?;
+        Ok(account_metas
+            .into_iter()
+            .filter(|account_meta| account_meta.is_writable == is_writable)
+            .map(|account_meta| account_meta.pubkey)
+            .collect())
+    }
+
+    /// Compute units to budget for the delegate or undelegate instruction of this swap
+    pub fn estimated_compute_units(&self, swap_params: &SwapParams) -> u32 {
+        match SwapDirection::from_source_mint(&swap_params.source_mint, &self.ssol_mint) {
+            SwapDirection::Delegate => DELEGATE_COMPUTE_UNITS,
+            SwapDirection::Undelegate => UNDELEGATE_COMPUTE_UNITS,
+        }
+    }
+
+    /// Mint that quote fees are denominated in
+    ///
+    /// Always sSOL, whichever the direction, as sSOL is common to every endoAVS pool so fees can
+    /// be compared across them. An undelegate fee is taken in the AVS token and converted at
+    /// the pool's exchange rate and decimals.
+    pub fn fee_mint(&self) -> Pubkey {
+        self.ssol_mint
+    }
+
+    // Trade fee charged on `amount` input tokens, zero when the pool has no fee configured
+    fn trading_fee(&self, amount: u64) -> Result<u64, SolayerAmmError> {
+        let fee = self
+            .state
+            .fees()
+            .trading_fee(amount.into())
+            .ok_or(SolayerAmmError::MathOverflow("trading fee"))?;
+        // the fee never exceeds the amount for a valid fee fraction
+        Ok(u64::try_from(fee).map_err(|_| SolayerAmmError::MathOverflow("trading fee"))?.min(amount))
+    }
+
+    // What is left of `amount` input tokens once the trade fee is taken
+    fn amount_after_trading_fee(&self, amount: u64) -> Result<u64, SolayerAmmError> {
+        amount
+            .checked_sub(self.trading_fee(amount)?)
+            .ok_or(SolayerAmmError::MathOverflow("amount after trading fee"))
+    }
+
+    // What is left of `amount` input tokens once the trade fee and the modeled fee of
+    // `avs_mint` are taken, the latter rounded up
+    fn amount_after_fees(&self, amount: u64, avs_mint: &Pubkey) -> Result<u64, SolayerAmmError> {
+        let amount = self.amount_after_trading_fee(amount)?;
+        let Some(&fee_bps) = self.avs_fee_bps.get(avs_mint) else {
+            return Ok(amount);
+        };
+        let fee = (u128::from(amount) * u128::from(fee_bps)).div_ceil(u128::from(MAX_BPS));
+        // fee_bps is at most MAX_BPS, so the fee never exceeds the amount
+        Ok(amount - fee as u64)
+    }
+
+    // Input required so that `out_amount` is left after both fees of `amount_after_fees`
+    fn amount_before_fees(&self, out_amount: u64, avs_mint: &Pubkey) -> Result<u64, SolayerAmmError> {
+        let out_amount = match self.avs_fee_bps.get(avs_mint) {
+            Some(&fee_bps) if fee_bps > 0 && out_amount > 0 => {
+                let kept = MAX_BPS - fee_bps;
+                if kept == 0 {
+                    return Err(SolayerAmmError::AvsFeeTakesAll { avs_mint: *avs_mint });
+                }
+                let in_amount = (u128::from(out_amount) * u128::from(MAX_BPS)).div_ceil(u128::from(kept));
+                u64::try_from(in_amount).map_err(|_| SolayerAmmError::MathOverflow("amount before AVS fee"))?
+            }
+            _ => out_amount,
+        };
+        self.amount_before_trading_fee(out_amount)
+    }
+
+    // Input required so that `out_amount` is left after the trade fee
+    fn amount_before_trading_fee(&self, out_amount: u64) -> Result<u64, SolayerAmmError> {
+        let fees = self.state.fees();
+        if fees.trade_fee_numerator == 0 || out_amount == 0 {
+            return Ok(out_amount);
+        }
+        let kept = fees
+            .trade_fee_denominator
+            .checked_sub(fees.trade_fee_numerator)
+            .filter(|kept| *kept > 0)
+            .ok_or(SolayerAmmError::InvalidTradeFee {
+                numerator: fees.trade_fee_numerator,
+                denominator: fees.trade_fee_denominator,
+            })?;
+        // round up, and the fee is at least one token whenever it is non-zero
+        let in_amount = (u128::from(out_amount) * u128::from(fees.trade_fee_denominator)).div_ceil(u128::from(kept));
+        let in_amount = in_amount.max(u128::from(out_amount) + 1);
+        // an out_amount close to u64::MAX needs an input that no longer fits a u64
+        u64::try_from(in_amount).map_err(|_| SolayerAmmError::MathOverflow("amount before trading fee"))
+    }
+
+    /// Token program owning `mint`, the one given to the builder or spl-token until update has
+    /// seen the mint
+    pub fn token_program(&self, mint: &Pubkey) -> Pubkey {
+        self.token_programs.get(mint).copied().unwrap_or_else(spl_token::id)
+    }
+
+    /// `get_swap_and_account_metas` writing the metas into `buf` instead of a new `Vec`
+    ///
+    /// `buf` is cleared first, reusing it across calls saves the per-swap allocation.
+    pub fn get_swap_and_account_metas_into(
+        &self,
+        swap_params: &SwapParams,
+        buf: &mut Vec<AccountMeta>,
+    ) -> Result<Swap> {
+        let (direction, account_metas) = self.checked_account_metas(swap_params)?;
+        buf.clear();
+        buf.extend(account_metas);
+        Ok(direction.swap())
+    }
+
+    /// `get_swap_and_account_metas` also returning the chosen direction and endoAVS
+    pub fn get_swap_and_account_metas_detailed(&self, swap_params: &SwapParams) -> Result<DetailedSwapAndAccountMetas> {
+        let (direction, account_metas) = self.checked_account_metas(swap_params)?;
+        Ok(DetailedSwapAndAccountMetas {
+            direction,
+            // second in the layout, see `build_account_metas`
+            endo_avs: account_metas[1].pubkey,
+            swap: direction.swap(),
+            account_metas: account_metas.to_vec(),
+        })
+    }
+
+    /// Checks the invariants of delegate/undelegate metas: the swap account count, the staker
+    /// first and the only signer, the writable flags of the layout and a token program last
+    pub fn validate_metas(metas: &[AccountMeta]) -> Result<(), SolayerAmmError> {
+        if metas.len() != SWAP_ACCOUNTS_LEN {
+            return Err(SolayerAmmError::InvalidAccountMetas {
+                index: metas.len(),
+                reason: "wrong number of accounts",
+            });
+        }
+        for (index, (meta, is_writable)) in metas.iter().zip(SWAP_ACCOUNTS_WRITABLE).enumerate() {
+            if meta.is_signer != (index == 0) {
+                return Err(SolayerAmmError::InvalidAccountMetas {
+                    index,
+                    reason: "only the staker signs",
+                });
+            }
+            if meta.is_writable != is_writable {
+                return Err(SolayerAmmError::InvalidAccountMetas {
+                    index,
+                    reason: "unexpected writable flag",
+                });
+            }
+        }
+        let token_program = metas[SWAP_ACCOUNTS_LEN - 1].pubkey;
+        if token_program != spl_token::id() && token_program != spl_token_2022::id() {
+            return Err(SolayerAmmError::InvalidAccountMetas {
+                index: SWAP_ACCOUNTS_LEN - 1,
+                reason: "not a token program",
+            });
+        }
+        Ok(())
+    }
+
+    // Validates the swap and builds its metas, shared by the allocating and buffer variants
+    fn checked_account_metas(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<(SwapDirection, [AccountMeta; SWAP_ACCOUNTS_LEN])> {
+        let _span = debug_span!(
+            "get_swap_and_account_metas",
+            source_mint = %swap_params.source_mint,
+            destination_mint = %swap_params.destination_mint,
+            in_amount = swap_params.in_amount,
+        )
+        .entered();
+        self.ensure_active()?;
+        let request = SolayerSwapRequest::from_swap_params(self, swap_params)?;
+        if !self.is_direction_enabled(request.direction) {
+            return Err(SolayerAmmError::DirectionDisabled(request.direction).into());
+        }
+        if swap_params.quote_mint_to_referrer.is_some() {
+            debug!("the delegation program takes no referral account, ignoring quote_mint_to_referrer");
+        }
+
+        // The instructions take a single amount that moves 1:1 between both sides, so ExactIn
+        // and ExactOut share the same account layout
+        let account_metas = self.build_account_metas(&request);
+        Self::validate_metas(&account_metas)?;
+        Ok((request.direction, account_metas))
+    }
+
+    // Both directions share one account layout, only the side each role sits on changes
+    fn build_account_metas(&self, request: &SolayerSwapRequest) -> [AccountMeta; SWAP_ACCOUNTS_LEN] {
+        let endo_avs = request.endo_avs;
+        debug!(direction = ?request.direction, %endo_avs, "building swap");
+        let delegated_token_vault = match self.delegated_token_vault {
+            Some(vault) if Some(endo_avs) == self.endo_avs => vault,
+            _ => self.derive_delegated_token_vault(&endo_avs),
+        };
+
+        [
+            // staker, the authority over the staker token accounts, see `validate_transfer_authority`
+            AccountMeta::new_readonly(request.staker, true),
+            // endoAvs
+            AccountMeta::new_readonly(endo_avs, false),
+            // avsTokenMint
+            AccountMeta::new(request.avs_token_mint, false),
+            // delegatedTokenVault
+            AccountMeta::new(delegated_token_vault, false),
+            // delegatedTokenMint
+            AccountMeta::new_readonly(request.delegated_token_mint, false),
+            // stakerDelegatedTokenAccount
+            AccountMeta::new(request.staker_delegated_token_account, false),
+            // stakerAvsTokenAccount
+            AccountMeta::new(request.staker_avs_token_account, false),
+            // tokenProgram, the one owning the AVS mint
+            AccountMeta::new_readonly(self.token_program(&request.avs_token_mint), false),
+        ]
+    }
+
+    fn derive_delegated_token_vault(&self, endo_avs: &Pubkey) -> Pubkey {
+        get_associated_token_address_with_program_id(endo_avs, &self.ssol_mint, &self.token_program(&self.ssol_mint))
+    }
+
+    // Re-derived whenever the endoAVS or the sSOL token program may have changed
+    fn refresh_delegated_token_vault(&mut self) {
+        self.delegated_token_vault = self.endo_avs.map(|endo_avs| self.derive_delegated_token_vault(&endo_avs));
+    }
+
+    fn token_accounts(&self) -> [Pubkey; 2] {
+        [self.state.token_a(), self.state.token_b()]
+    }
+
+    /// The endoAVS serving `avs_mint`, among the built-in set and the builder's overrides
+    ///
+    /// Resolved for every quote and swap: the pool's own AVS mint is compared against the cached
+    /// endoAVS without hashing, only other mints (AVS -> AVS) go through the map.
+    pub fn endo_avs_address(&self, avs_mint: &Pubkey) -> Result<Pubkey, SolayerAmmError> {
+        if *avs_mint == self.avs_mint() {
+            if let Some(endo_avs) = self.endo_avs {
+                return Ok(endo_avs);
+            }
+        }
+        self.avs_addresses
+            .get(avs_mint)
+            .copied()
+            .ok_or(SolayerAmmError::UnknownAvsMint(*avs_mint))
+    }
+
+    // A valid pair has sSOL on exactly one side and this pool's AVS mint on the other, a known
+    // AVS mint of another pool is only reachable through `avs_to_avs`
+    fn validate_mint_pair(&self, input_mint: &Pubkey, output_mint: &Pubkey) -> Result<(), SolayerAmmError> {
+        let avs_mint = self.validate_known_avs_pair(input_mint, output_mint)?;
+        if !self.reserve_mints.contains(&avs_mint) {
+            return Err(SolayerAmmError::UnsupportedMintPair {
+                input_mint: *input_mint,
+                output_mint: *output_mint,
+            });
+        }
+        Ok(())
+    }
+
+    // sSOL on exactly one side and a known AVS mint, of any pool, on the other, returning the latter
+    fn validate_known_avs_pair(&self, input_mint: &Pubkey, output_mint: &Pubkey) -> Result<Pubkey, SolayerAmmError> {
+        if input_mint == output_mint {
+            return Err(SolayerAmmError::SameMint(*input_mint));
+        }
+        let avs_mint = match (*input_mint == self.ssol_mint, *output_mint == self.ssol_mint) {
+            (true, false) => output_mint,
+            (false, true) => input_mint,
+            _ => {
+                return Err(SolayerAmmError::UnsupportedMintPair {
+                    input_mint: *input_mint,
+                    output_mint: *output_mint,
+                })
+            }
+        };
+        self.endo_avs_address(avs_mint)?;
+        Ok(*avs_mint)
+    }
+}
+
+impl Amm for SolayerEndoAVSAmm {
+    fn from_keyed_account(keyed_account: &KeyedAccount, amm_context: &AmmContext) -> Result<Self> {
+        SolayerEndoAVSAmmBuilder::new().build(keyed_account, amm_context)
+    }
+
+    fn label(&self) -> String {
+        self.label.clone()
+    }
+
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    /// `[token_a_mint, token_b_mint]` as stored in the pool, so sSOL may be at either index,
+    /// use `sol_mint`/`avs_mint` to pick a side
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        self.reserve_mints.to_vec()
+    }
+
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        let mut accounts = self.token_accounts().to_vec();
+        // the mints tell which token program each side uses
+        accounts.extend(self.reserve_mints);
+        accounts.extend(self.endo_avs);
+        accounts.extend(self.destination_ata.map(|(ata, _)| ata));
+        accounts
+    }
+
+    /// Accounts missing from `account_map`, e.g. after an RPC race, are skipped with a debug log
+    /// and leave the values from the last update in place, so they may be stale. Reserves only
+    /// count as loaded once both token accounts have been seen in one update.
+    fn update(&mut self, account_map: &AccountMap) -> Result<()> {
+        // reserves follow the order of the token accounts: token_a, token_b
+        let token_accounts = self.token_accounts();
+        let mut all_reserves_found = true;
+        for (reserve, address) in self.reserves.iter_mut().zip(token_accounts) {
+            let Some(account) = account_map.get(&address) else {
+                debug!(%address, "token account missing from the account map, keeping its last reserve");
+                all_reserves_found = false;
+                continue;
+            };
+            *reserve = unpack_token_account(&address, &account.data)?.amount.into();
+        }
+        self.reserves_loaded |= all_reserves_found;
+
+        for (mint, decimals) in self.reserve_mints.into_iter().zip(&mut self.mint_decimals) {
+            if let Some(account) = account_map.get(&mint) {
+                if account.owner == spl_token::id() || account.owner == spl_token_2022::id() {
+                    self.token_programs.insert(mint, account.owner);
+                    // the base mint layout is the same under both programs
+                    let mint_state = StateWithExtensions::<Mint>::unpack(&account.data).map_err(|err| {
+                        SolayerAmmError::InvalidAccountData {
+                            account: mint,
+                            reason: err.to_string(),
+                        }
+                    })?;
+                    *decimals = Some(mint_state.base.decimals);
+                }
+            }
+        }
+
+        self.refresh_delegated_token_vault();
+
+        if let Some((ata, exists)) = &mut self.destination_ata {
+            *exists = Some(account_map.get(ata).is_some_and(|account| !account.data.is_empty()));
+        }
+
+        // The endoAVS carries no rate or fee of its own, delegation stays 1:1,
+        // but a closed endoAVS can no longer delegate or undelegate
+        if let Some(endo_avs) = self.endo_avs {
+            match account_map.get(&endo_avs) {
+                Some(account) if account.data.is_empty() => {
+                    return Err(SolayerAmmError::EndoAvsClosed(endo_avs).into());
+                }
+                Some(_) => {}
+                None => debug!(%endo_avs, "endoAVS missing from the account map, skipping its check"),
+            }
+        }
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        let _span = debug_span!(
+            "quote",
+            input_mint = %quote_params.input_mint,
+            output_mint = %quote_params.output_mint,
+            amount = quote_params.amount,
+        )
+        .entered();
+        self.ensure_active()?;
+        self.ensure_amount_in_range(quote_params.amount)?;
+        if self.is_avs_to_avs(&quote_params.input_mint, &quote_params.output_mint) {
+            return self.quote_avs_to_avs(quote_params);
+        }
+        match self.validate_mint_pair(&quote_params.input_mint, &quote_params.output_mint) {
+            Ok(()) => {}
+            Err(SolayerAmmError::UnknownAvsMint(_) | SolayerAmmError::UnsupportedMintPair { .. })
+                if self.unknown_mint_policy == UnknownMintPolicy::Skip =>
+            {
+                return Ok(Quote::default());
+            }
+            Err(err) => return Err(err.into()),
+        }
+        self.ensure_direction_enabled(&quote_params.input_mint)?;
+        // a zero amount would only lead to a no-op transaction, so refuse it rather than quote 0 -> 0
+        if quote_params.amount == 0 {
+            return Err(SolayerAmmError::ZeroAmount.into());
+        }
+        // only known once update has loaded the reserves
+        if self.reserves_loaded {
+            let output_index = if quote_params.output_mint == self.reserve_mints[0] { 0 } else { 1 };
+            if self.reserves[output_index] == 0 {
+                return Err(SolayerAmmError::NoLiquidity.into());
+            }
+        }
+
+        // endoAVS token to sSOL is 1:1 unless an exchange rate is set, less the pool's trade fee
+        // if it charges one and any modeled AVS fee. Fees are taken on the input side, before the rate.
+        let direction = SwapDirection::from_source_mint(&quote_params.input_mint, &self.ssol_mint);
+        let avs_mint = match direction {
+            SwapDirection::Delegate => &quote_params.output_mint,
+            SwapDirection::Undelegate => &quote_params.input_mint,
+        };
+        // 1:1 is on whole tokens, base units are rescaled when the mints' decimals differ
+        let decimals = self.quote_decimals(&quote_params.input_mint);
+        let (in_amount, after_fee_amount, out_amount) = match quote_params.swap_mode {
+            // amount is what the user pays in
+            SwapMode::ExactIn => {
+                let after_fee_amount = self.amount_after_fees(quote_params.amount, avs_mint)?;
+                let mut out_amount = match self.exchange_rate {
+                    Some(rate) => rate.convert(after_fee_amount, direction, false)?,
+                    None => after_fee_amount,
+                };
+                if let Some((input_decimals, output_decimals)) = decimals {
+                    out_amount = scale_decimals(out_amount, input_decimals, output_decimals, false)?;
+                }
+                (quote_params.amount, after_fee_amount, out_amount)
+            }
+            // amount is what the user wants out
+            SwapMode::ExactOut => {
+                let mut amount = quote_params.amount;
+                if let Some((input_decimals, output_decimals)) = decimals {
+                    amount = scale_decimals(amount, output_decimals, input_decimals, true)?;
+                }
+                let after_fee_amount = match self.exchange_rate {
+                    Some(rate) => rate.convert(amount, direction.reverse(), true)?,
+                    None => amount,
+                };
+                let in_amount = self.amount_before_fees(after_fee_amount, avs_mint)?;
+                (in_amount, after_fee_amount, quote_params.amount)
+            }
+        };
+
+        // The rate is fixed, so there is no price impact and the amounts are exact:
+        // the minimums are the amounts themselves and the fee is the trade fee
+        let input_fee_amount = in_amount
+            .checked_sub(after_fee_amount)
+            .ok_or(SolayerAmmError::MathOverflow("fee amount"))?;
+        // taken in the input token, reported in sSOL like `fee_mint` says
+        let fee_amount = match direction {
+            SwapDirection::Delegate => input_fee_amount,
+            SwapDirection::Undelegate => {
+                let mut fee_amount = match self.exchange_rate {
+                    Some(rate) => rate.convert(input_fee_amount, direction, false)?,
+                    None => input_fee_amount,
+                };
+                if let Some((input_decimals, output_decimals)) = decimals {
+                    fee_amount = scale_decimals(fee_amount, input_decimals, output_decimals, false)?;
+                }
+                fee_amount
+            }
+        };
+        let quote = Quote {
+            min_in_amount: Some(in_amount),
+            min_out_amount: Some(out_amount),
+            in_amount,
+            out_amount,
+            fee_amount,
+            fee_mint: self.fee_mint(),
+            fee_pct: Decimal::from(input_fee_amount) / Decimal::from(in_amount),
+        };
+        debug!(?quote, "quoted");
+        Ok(quote)
+    }
+
+    /// Indicates which Swap has to be performed along with all the necessary account metas
+    ///
+    /// The metas carry no amount: the router passes `swap_params.in_amount` to the delegate or
+    /// undelegate instruction, so only that amount moves rather than the whole token balance.
+    /// If it exceeds the staker's balance the instruction fails on-chain instead of clamping.
+    /// AVS -> AVS takes two instructions and is built with `get_avs_to_avs_swap_legs` instead.
+    ///
+    /// Delegate and undelegate have no referral account, so `quote_mint_to_referrer` is ignored:
+    /// a platform fee has to be taken by the router around the swap.
+    fn get_swap_and_account_metas(&self, swap_params: &SwapParams) -> Result<SwapAndAccountMetas> {
+        let (direction, account_metas) = self.checked_account_metas(swap_params)?;
+        Ok(SwapAndAccountMetas {
+            swap: direction.swap(),
+            account_metas: account_metas.to_vec(),
+        })
+    }
+
+    // `watch_destination_ata` adds or swaps an account to update at any time
+    fn has_dynamic_accounts(&self) -> bool {
+        true
+    }
+
+    // The reserve mints come straight from the pool state, but quotes hang on the reserves and
+    // mint decimals read in update, see `requires_update_for_quote`
+    fn requires_update_for_reserve_mints(&self) -> bool {
+        self.requires_update_for_quote()
+    }
+
+    // Indicates that whether ExactOut mode is supported
+    fn supports_exact_out(&self) -> bool {
+        true
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    // The delegation program executing the swap and the token program in its account metas
+    fn program_dependencies(&self) -> Vec<(Pubkey, String)> {
+        let token_program = self.token_program(&self.avs_mint());
+        let token_program_name = if token_program == spl_token_2022::id() {
+            "spl_token_2022"
+        } else {
+            "spl_token"
+        };
+        vec![
+            (self.program_id, "solayer_endo_avs".into()),
+            (token_program, token_program_name.into()),
+        ]
+    }
+
+    // Inactive before the activation epoch set on the builder
+    fn is_active(&self) -> bool {
+        self.ensure_active().is_ok()
+    }
+
+    /// The swap accounts, plus the ones needed to create the destination ATA when a watched
+    /// ATA (see `watch_destination_ata`) was missing at the last update
+    fn get_accounts_len(&self) -> usize {
+        match self.destination_ata {
+            Some((_, Some(false))) => SWAP_ACCOUNTS_LEN + ATA_CREATION_ACCOUNTS_LEN,
+            _ => SWAP_ACCOUNTS_LEN,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::program_option::COption;
+    use spl_token_2022::extension::{
+        immutable_owner::ImmutableOwner, BaseStateWithExtensionsMut, StateWithExtensionsMut,
+    };
+    use spl_token_2022::state::AccountState;
+    use spl_token_swap::solana_program::pubkey::Pubkey as SwapPubkey;
+
+    use super::*;
+
+    const POOL_KEY: Pubkey = Pubkey::new_from_array([1; 32]);
+    const TOKEN_A: Pubkey = Pubkey::new_from_array([2; 32]);
+    const TOKEN_B: Pubkey = Pubkey::new_from_array([3; 32]);
+    static JUPITER_PROGRAM_ID: Pubkey = Pubkey::new_from_array([4; 32]);
+
+    // spl-token-swap is built on an older solana-program with its own Pubkey
+    fn swap_pubkey(pubkey: Pubkey) -> SwapPubkey {
+        SwapPubkey::new_from_array(pubkey.to_bytes())
+    }
+
+    fn swap_state(token_a_mint: Pubkey, token_b_mint: Pubkey) -> SwapV1 {
+        SwapV1 {
+            is_initialized: true,
+            token_a: swap_pubkey(TOKEN_A),
+            token_b: swap_pubkey(TOKEN_B),
+            token_a_mint: swap_pubkey(token_a_mint),
+            token_b_mint: swap_pubkey(token_b_mint),
+            ..SwapV1::default()
+        }
+    }
+
+    // The version byte followed by the packed state, as stored on-chain
+    fn swap_account(state: &SwapV1, owner: Pubkey) -> Account {
+        let mut data = vec![0; 1 + SwapV1::LEN];
+        data[0] = SWAP_V1_VERSION;
+        state.pack_into_slice(&mut data[1..]);
+        Account {
+            lamports: 0,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn amm_context() -> AmmContext {
+        AmmContext {
+            clock_ref: ClockRef::default(),
+        }
+    }
+
+    // Registers the AVS the tests trade explicitly, so they hold without the built-in mainnet set too
+    fn test_builder() -> SolayerEndoAVSAmmBuilder {
+        SolayerEndoAVSAmmBuilder::new().avs_overrides(HashMap::from([
+            (BYBIT_AVS_MINT, BYBIT_AVS_ADDRESS),
+            (OKX_AVS_MINT, OKX_AVS_ADDRESS),
+        ]))
+    }
+
+    // sSOL as token_a, the Bybit AVS token as token_b
+    fn bybit_amm() -> SolayerEndoAVSAmm {
+        bybit_amm_with(test_builder())
+    }
+
+    fn bybit_amm_with(builder: SolayerEndoAVSAmmBuilder) -> SolayerEndoAVSAmm {
+        builder.build_with_state(
+            POOL_KEY,
+            swap_state(SOLAYER_SOL, BYBIT_AVS_MINT),
+            SOLAYER_DELEGATION_PROGRAM,
+            &amm_context(),
+        )
+    }
+
+    fn quote_params(amount: u64, input_mint: Pubkey, output_mint: Pubkey) -> QuoteParams {
+        QuoteParams {
+            amount,
+            input_mint,
+            output_mint,
+            swap_mode: SwapMode::ExactIn,
+        }
+    }
+
+    fn swap_params(source_mint: Pubkey, destination_mint: Pubkey) -> SwapParams<'static, 'static> {
+        SwapParams {
+            swap_mode: SwapMode::ExactIn,
+            in_amount: 1_000_000_000,
+            out_amount: 1_000_000_000,
+            source_mint,
+            destination_mint,
+            source_token_account: Pubkey::new_unique(),
+            destination_token_account: Pubkey::new_unique(),
+            token_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            jupiter_program_id: &JUPITER_PROGRAM_ID,
+            missing_dynamic_accounts_as_default: false,
+        }
+    }
+
+    // Token-2022 account with the ImmutableOwner extension ATAs get, longer than a spl-token one
+    fn token_2022_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+        let len =
+            ExtensionType::try_calculate_account_len::<Token2022Account>(&[ExtensionType::ImmutableOwner]).unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<Token2022Account>::unpack_uninitialized(&mut data).unwrap();
+        state.init_extension::<ImmutableOwner>(true).unwrap();
+        state.base = Token2022Account {
+            mint,
+            owner,
+            amount,
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            ..Token2022Account::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        Account {
+            lamports: 0,
+            data,
+            owner: spl_token_2022::id(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn mint_account(decimals: u8, supply: u64, token_program: Pubkey) -> Account {
+        let mut data = vec![0; Mint::LEN];
+        Mint {
+            decimals,
+            supply,
+            is_initialized: true,
+            ..Mint::default()
+        }
+        .pack_into_slice(&mut data);
+        Account {
+            lamports: 0,
+            data,
+            owner: token_program,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..TokenAccount::default()
+        }
+        .pack_into_slice(&mut data);
+        Account {
+            lamports: 0,
+            data,
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    // Loads both reserves, in storage order, through update
+    fn load_reserves(amm: &mut SolayerEndoAVSAmm, reserves: [u64; 2]) {
+        let [token_a, token_b] = amm.token_accounts();
+        amm.update(&account_map([
+            (token_a, token_account(amm.reserve_mints[0], POOL_KEY, reserves[0])),
+            (token_b, token_account(amm.reserve_mints[1], POOL_KEY, reserves[1])),
+        ]))
+        .unwrap();
+    }
+
+    // Quote has no PartialEq
+    fn quote_fields(quote: &Quote) -> (u64, u64, u64, Pubkey, Decimal) {
+        (
+            quote.in_amount,
+            quote.out_amount,
+            quote.fee_amount,
+            quote.fee_mint,
+            quote.fee_pct,
+        )
+    }
+
+    fn account_map(accounts: impl IntoIterator<Item = (Pubkey, Account)>) -> AccountMap {
+        accounts.into_iter().collect()
+    }
+
+    #[test]
+    fn test_token_2022_avs_mint_uses_token_2022_program() {
+        let mut amm = bybit_amm();
+        amm.update(&account_map([(
+            BYBIT_AVS_MINT,
+            mint_account(9, 0, spl_token_2022::id()),
+        )]))
+        .unwrap();
+
+        let SwapAndAccountMetas { account_metas, .. } = amm
+            .get_swap_and_account_metas(&swap_params(SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(account_metas[SWAP_ACCOUNTS_LEN - 1].pubkey, spl_token_2022::id());
+    }
+
+    #[test]
+    fn test_token_2022_accounts_with_extensions() {
+        let mut amm = bybit_amm();
+        let vault = token_2022_account(SOLAYER_SOL, Pubkey::new_unique(), 5);
+        assert!(vault.data.len() > TokenAccount::LEN);
+        amm.update(&account_map([
+            (TOKEN_A, vault),
+            (TOKEN_B, token_2022_account(BYBIT_AVS_MINT, Pubkey::new_unique(), 7)),
+        ]))
+        .unwrap();
+        assert_eq!(amm.reserves(), [5, 7]);
+
+        let swap_params = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+        let user_accounts = account_map([
+            (
+                swap_params.source_token_account,
+                token_2022_account(SOLAYER_SOL, swap_params.token_transfer_authority, 10),
+            ),
+            (
+                swap_params.destination_token_account,
+                token_2022_account(BYBIT_AVS_MINT, swap_params.token_transfer_authority, 0),
+            ),
+        ]);
+        SolayerEndoAVSAmm::validate_swap_token_accounts(&swap_params, &user_accounts).unwrap();
+        SolayerEndoAVSAmm::validate_transfer_authority(&swap_params, &user_accounts).unwrap();
+    }
+
+    #[test]
+    fn test_exact_in_and_exact_out_share_metas() {
+        let amm = bybit_amm();
+        for (source_mint, destination_mint) in [(SOLAYER_SOL, BYBIT_AVS_MINT), (BYBIT_AVS_MINT, SOLAYER_SOL)] {
+            let exact_in = swap_params(source_mint, destination_mint);
+            let exact_out = SwapParams {
+                swap_mode: SwapMode::ExactOut,
+                ..exact_in
+            };
+
+            let exact_in = amm.get_swap_and_account_metas(&exact_in).unwrap();
+            let exact_out = amm.get_swap_and_account_metas(&exact_out).unwrap();
+            assert_eq!(exact_in.swap, exact_out.swap);
+            assert_eq!(exact_in.account_metas, exact_out.account_metas);
+        }
+    }
+
+    #[test]
+    fn test_activation_epoch_follows_the_context_clock() {
+        let amm_context = amm_context();
+        let amm = test_builder().activation_epoch(10).build_with_state(
+            POOL_KEY,
+            swap_state(SOLAYER_SOL, BYBIT_AVS_MINT),
+            SOLAYER_DELEGATION_PROGRAM,
+            &amm_context,
+        );
+        let quote_params = quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT);
+
+        amm_context.clock_ref.epoch.store(9, Ordering::Relaxed);
+        assert!(!amm.is_active());
+        assert!(!amm.can_quote(&SOLAYER_SOL, &BYBIT_AVS_MINT));
+        let err = amm.quote(&quote_params).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::NotYetActive {
+                epoch: 9,
+                activation_epoch: 10
+            })
+        );
+
+        amm_context.clock_ref.epoch.store(10, Ordering::Relaxed);
+        assert!(amm.is_active());
+        assert_eq!(amm.quote(&quote_params).unwrap().out_amount, 1_000);
+    }
+
+    #[test]
+    fn test_undelegate_takes_no_ticket_account() {
+        let amm = bybit_amm();
+        let delegate = amm
+            .get_swap_and_account_metas(&swap_params(SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        let undelegate = amm
+            .get_swap_and_account_metas(&swap_params(BYBIT_AVS_MINT, SOLAYER_SOL))
+            .unwrap();
+
+        // undelegation settles immediately, both directions take the same accounts
+        assert_eq!(delegate.account_metas.len(), SWAP_ACCOUNTS_LEN);
+        assert_eq!(undelegate.account_metas.len(), SWAP_ACCOUNTS_LEN);
+    }
+
+    #[test]
+    fn test_debug_and_display_summarize_the_pool() {
+        let amm = bybit_amm();
+        for formatted in [format!("{amm:?}"), amm.to_string()] {
+            assert!(formatted.contains(&POOL_KEY.to_string()), "{formatted}");
+            assert!(formatted.contains(&SOLAYER_SOL.to_string()), "{formatted}");
+            assert!(formatted.contains(&BYBIT_AVS_MINT.to_string()), "{formatted}");
+        }
+    }
+
+    #[test]
+    fn test_custom_ssol_mint_drives_direction() {
+        let devnet_ssol = Pubkey::new_unique();
+        let amm = test_builder().ssol_mint(devnet_ssol).build_with_state(
+            POOL_KEY,
+            // sSOL as token_b, so the storage order alone doesn't give the direction
+            swap_state(BYBIT_AVS_MINT, devnet_ssol),
+            SOLAYER_DELEGATION_PROGRAM,
+            &amm_context(),
+        );
+
+        assert_eq!(amm.sol_mint(), devnet_ssol);
+        assert_eq!(amm.avs_mint(), BYBIT_AVS_MINT);
+        assert_eq!(amm.fee_mint(), devnet_ssol);
+        let delegate = amm
+            .get_swap_and_account_metas_detailed(&swap_params(devnet_ssol, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(delegate.direction, SwapDirection::Delegate);
+        let undelegate = amm
+            .get_swap_and_account_metas_detailed(&swap_params(BYBIT_AVS_MINT, devnet_ssol))
+            .unwrap();
+        assert_eq!(undelegate.direction, SwapDirection::Undelegate);
+        // mainnet sSOL is just another unknown mint there
+        assert!(amm.quote(&quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT)).is_err());
+    }
+
+    #[test]
+    fn test_is_endo_avs_account_for_another_deployment() {
+        let devnet_program = Pubkey::new_unique();
+        let devnet_ssol = Pubkey::new_unique();
+        let devnet_account = swap_account(&swap_state(devnet_ssol, BYBIT_AVS_MINT), devnet_program);
+
+        assert!(is_endo_avs_account(&devnet_account, &devnet_program, &devnet_ssol));
+        assert!(!is_solayer_endo_avs_account(&devnet_account));
+        assert!(!is_endo_avs_account(&devnet_account, &devnet_program, &SOLAYER_SOL));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_json_round_trip_quotes_the_same() {
+        let mut amm =
+            bybit_amm_with(test_builder().token_programs(HashMap::from([(BYBIT_AVS_MINT, spl_token_2022::id())])));
+        load_reserves(&mut amm, [5_000_000_000, 3_000_000_000]);
+
+        let json = serde_json::to_string(&amm.to_snapshot()).unwrap();
+        let restored = SolayerEndoAVSAmm::from_snapshot(serde_json::from_str(&json).unwrap(), &amm_context()).unwrap();
+
+        assert_eq!(restored.reserves, amm.reserves);
+        assert_eq!(restored.endo_avs, Some(BYBIT_AVS_ADDRESS));
+        for (input_mint, output_mint) in [(SOLAYER_SOL, BYBIT_AVS_MINT), (BYBIT_AVS_MINT, SOLAYER_SOL)] {
+            let quote_params = quote_params(1_000_000_000, input_mint, output_mint);
+            assert_eq!(
+                quote_fields(&restored.quote(&quote_params).unwrap()),
+                quote_fields(&amm.quote(&quote_params).unwrap())
+            );
+        }
+        assert_eq!(restored.token_program(&BYBIT_AVS_MINT), spl_token_2022::id());
+    }
+
+    // 1,050 Bybit AVS against 1,000 sSOL in the endoAVS vault, e.g. after a donation to the vault
+    fn bybit_accounts_with_a_donated_vault(amm: &SolayerEndoAVSAmm) -> AccountMap {
+        let vault = amm.derive_delegated_token_vault(&BYBIT_AVS_ADDRESS);
+        account_map([
+            (SOLAYER_SOL, mint_account(9, 2_000_000_000_000, spl_token::id())),
+            (BYBIT_AVS_MINT, mint_account(9, 1_050_000_000_000, spl_token::id())),
+            (vault, token_account(SOLAYER_SOL, BYBIT_AVS_ADDRESS, 1_000_000_000_000)),
+        ])
+    }
+
+    #[test]
+    fn test_update_never_infers_an_exchange_rate() {
+        let mut amm = bybit_amm();
+        assert!(!amm
+            .get_accounts_to_update()
+            .contains(&amm.derive_delegated_token_vault(&BYBIT_AVS_ADDRESS)));
+        amm.update(&bybit_accounts_with_a_donated_vault(&amm)).unwrap();
+
+        assert_eq!(amm.exchange_rate(), None);
+        let delegate = amm
+            .quote(&quote_params(1_000_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(delegate.out_amount, 1_000_000);
+        let undelegate = amm
+            .quote(&quote_params(1_000_000, BYBIT_AVS_MINT, SOLAYER_SOL))
+            .unwrap();
+        assert_eq!(undelegate.out_amount, 1_000_000);
+    }
+
+    #[test]
+    fn test_builder_exchange_rate_survives_update() {
+        let rate = ExchangeRate {
+            numerator: 105,
+            denominator: 100,
+        };
+        let mut amm = bybit_amm_with(test_builder().exchange_rate(rate).unwrap());
+        amm.update(&bybit_accounts_with_a_donated_vault(&amm)).unwrap();
+        assert_eq!(amm.exchange_rate(), Some(rate));
+
+        let delegate = amm
+            .quote(&quote_params(1_000_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(delegate.out_amount, 1_050_000);
+        let undelegate = amm
+            .quote(&quote_params(1_000_000, BYBIT_AVS_MINT, SOLAYER_SOL))
+            .unwrap();
+        assert_eq!(undelegate.out_amount, 952_380);
+
+        // cleared, quotes go back to 1:1 and stay there
+        amm.set_exchange_rate(None).unwrap();
+        amm.update(&bybit_accounts_with_a_donated_vault(&amm)).unwrap();
+        assert_eq!(amm.exchange_rate(), None);
+    }
+
+    #[test]
+    fn test_builder_rejects_a_zero_exchange_rate() {
+        let rate = ExchangeRate {
+            numerator: 0,
+            denominator: 1,
+        };
+        assert_eq!(
+            test_builder().exchange_rate(rate).err(),
+            Some(SolayerAmmError::InvalidExchangeRate {
+                numerator: 0,
+                denominator: 1,
+            })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_keeps_the_exchange_rate() {
+        let mut amm = bybit_amm();
+        amm.set_exchange_rate(Some(ExchangeRate {
+            numerator: 105,
+            denominator: 100,
+        }))
+        .unwrap();
+
+        let restored = SolayerEndoAVSAmm::from_snapshot(amm.to_snapshot(), &amm_context()).unwrap();
+        assert_eq!(restored.exchange_rate(), amm.exchange_rate());
+        let quote_params = quote_params(1_000_000, BYBIT_AVS_MINT, SOLAYER_SOL);
+        assert_eq!(restored.quote(&quote_params).unwrap().out_amount, 952_380);
+    }
+
+    #[test]
+    fn test_requires_update_for_quote_until_reserves_and_mints_are_read() {
+        let mut amm = bybit_amm();
+        assert!(amm.requires_update_for_quote());
+
+        amm.update(&account_map([
+            (SOLAYER_SOL, mint_account(9, 0, spl_token::id())),
+            (BYBIT_AVS_MINT, mint_account(6, 0, spl_token::id())),
+        ]))
+        .unwrap();
+        assert!(amm.requires_update_for_quote());
+
+        load_reserves(&mut amm, [1_000, 1_000]);
+        assert!(!amm.requires_update_for_quote());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_keeps_the_mint_decimals() {
+        let mut amm = bybit_amm();
+        amm.update(&account_map([
+            (SOLAYER_SOL, mint_account(9, 0, spl_token::id())),
+            (BYBIT_AVS_MINT, mint_account(6, 0, spl_token::id())),
+        ]))
+        .unwrap();
+
+        load_reserves(&mut amm, [1_000_000_000, 1_000_000]);
+
+        let restored = SolayerEndoAVSAmm::from_snapshot(amm.to_snapshot(), &amm_context()).unwrap();
+        assert!(!restored.requires_update_for_quote());
+        let quote = restored
+            .quote(&quote_params(1_000_000_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(quote.out_amount, 1_000_000);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_keeps_a_disabled_direction() {
+        let mut amm = bybit_amm();
+        amm.set_direction_enabled(SwapDirection::Undelegate, false);
+
+        let restored = SolayerEndoAVSAmm::from_snapshot(amm.to_snapshot(), &amm_context()).unwrap();
+        assert!(restored.can_quote(&SOLAYER_SOL, &BYBIT_AVS_MINT));
+        assert!(!restored.can_quote(&BYBIT_AVS_MINT, &SOLAYER_SOL));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_keeps_avs_to_avs() {
+        let amm = bybit_amm_with(test_builder().avs_to_avs(true));
+
+        let restored = SolayerEndoAVSAmm::from_snapshot(amm.to_snapshot(), &amm_context()).unwrap();
+        let quote = restored
+            .quote(&quote_params(1_000_000, OKX_AVS_MINT, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(quote.out_amount, 1_000_000);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_keeps_the_activation_epoch() {
+        let amm = bybit_amm_with(test_builder().activation_epoch(500));
+
+        let amm_context = amm_context();
+        let restored = SolayerEndoAVSAmm::from_snapshot(amm.to_snapshot(), &amm_context).unwrap();
+        assert!(!restored.is_active());
+        amm_context.clock_ref.epoch.store(500, Ordering::Relaxed);
+        assert!(restored.is_active());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_keeps_the_amount_bounds() {
+        let amm = bybit_amm_with(test_builder().min_amount(1_000).max_amount(1_000_000));
+
+        let restored = SolayerEndoAVSAmm::from_snapshot(amm.to_snapshot(), &amm_context()).unwrap();
+        for amount in [999, 1_000_001] {
+            assert!(restored
+                .quote(&quote_params(amount, SOLAYER_SOL, BYBIT_AVS_MINT))
+                .is_err());
+        }
+        assert!(restored
+            .quote(&quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_keeps_the_undelegation_cooldown() {
+        let amm = bybit_amm_with(test_builder().undelegation_cooldown_slots(100));
+
+        let restored = SolayerEndoAVSAmm::from_snapshot(amm.to_snapshot(), &amm_context()).unwrap();
+        assert_eq!(restored.undelegation_available_slot(1_000), Some(1_100));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_keeps_the_unknown_mint_policy() {
+        let amm = bybit_amm_with(test_builder().unknown_mint_policy(UnknownMintPolicy::Skip));
+
+        let json = serde_json::to_string(&amm.to_snapshot()).unwrap();
+        let restored = SolayerEndoAVSAmm::from_snapshot(serde_json::from_str(&json).unwrap(), &amm_context()).unwrap();
+        let quote = restored
+            .quote(&quote_params(1_000, SOLAYER_SOL, Pubkey::new_unique()))
+            .unwrap();
+        assert_eq!(quote.out_amount, 0);
+    }
+
+    fn bybit_amm_with_fee_bps(fee_bps: u16) -> SolayerEndoAVSAmm {
+        bybit_amm_with(
+            test_builder()
+                .avs_fee_bps(HashMap::from([(BYBIT_AVS_MINT, fee_bps)]))
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_avs_fee_bps_reduces_the_output() {
+        let amm = bybit_amm_with_fee_bps(30);
+
+        for (input_mint, output_mint) in [(SOLAYER_SOL, BYBIT_AVS_MINT), (BYBIT_AVS_MINT, SOLAYER_SOL)] {
+            let quote = amm.quote(&quote_params(1_000_000, input_mint, output_mint)).unwrap();
+            assert_eq!(quote.out_amount, 997_000);
+            assert_eq!(quote.fee_amount, 3_000);
+        }
+        // other AVS keep no fee
+        let okx = test_builder()
+            .avs_fee_bps(HashMap::from([(BYBIT_AVS_MINT, 30)]))
+            .unwrap()
+            .build_with_state(
+                POOL_KEY,
+                swap_state(SOLAYER_SOL, OKX_AVS_MINT),
+                SOLAYER_DELEGATION_PROGRAM,
+                &amm_context(),
+            );
+        let quote = okx.quote(&quote_params(1_000_000, SOLAYER_SOL, OKX_AVS_MINT)).unwrap();
+        assert_eq!(quote.out_amount, 1_000_000);
+    }
+
+    #[test]
+    fn test_avs_fee_bps_above_max_is_rejected() {
+        let err = test_builder()
+            .avs_fee_bps(HashMap::from([(BYBIT_AVS_MINT, MAX_BPS + 1)]))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            SolayerAmmError::InvalidAvsFeeBps {
+                avs_mint: BYBIT_AVS_MINT,
+                fee_bps: MAX_BPS + 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_exact_out_through_a_full_avs_fee() {
+        let amm = bybit_amm_with_fee_bps(MAX_BPS);
+        let quote_params = QuoteParams {
+            swap_mode: SwapMode::ExactOut,
+            ..quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT)
+        };
+
+        let err = amm.quote(&quote_params).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::AvsFeeTakesAll {
+                avs_mint: BYBIT_AVS_MINT
+            })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_keeps_the_avs_fee_bps() {
+        let amm = bybit_amm_with_fee_bps(30);
+
+        let json = serde_json::to_string(&amm.to_snapshot()).unwrap();
+        let restored = SolayerEndoAVSAmm::from_snapshot(serde_json::from_str(&json).unwrap(), &amm_context()).unwrap();
+        let quote = restored
+            .quote(&quote_params(1_000_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(quote.out_amount, 997_000);
+    }
+
+    #[test]
+    fn test_fees_are_reported_in_ssol_both_ways() {
+        let mut amm = bybit_amm_with_fee_bps(30);
+        amm.update(&account_map([
+            (SOLAYER_SOL, mint_account(9, 0, spl_token::id())),
+            (BYBIT_AVS_MINT, mint_account(6, 0, spl_token::id())),
+        ]))
+        .unwrap();
+
+        let quote = amm
+            .quote(&quote_params(1_000_000_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(quote.fee_mint, SOLAYER_SOL);
+        assert_eq!(quote.fee_amount, 3_000_000);
+
+        // 1 AVS in, 0.003 AVS fee, i.e. 0.003 sSOL
+        let quote = amm
+            .quote(&quote_params(1_000_000, BYBIT_AVS_MINT, SOLAYER_SOL))
+            .unwrap();
+        assert_eq!(quote.out_amount, 997_000_000);
+        assert_eq!(quote.fee_mint, SOLAYER_SOL);
+        assert_eq!(quote.fee_amount, 3_000_000);
+        assert_eq!(quote.fee_pct, Decimal::new(3, 3));
+
+        amm.set_exchange_rate(Some(ExchangeRate {
+            numerator: 2,
+            denominator: 1,
+        }))
+        .unwrap();
+        let quote = amm
+            .quote(&quote_params(1_000_000, BYBIT_AVS_MINT, SOLAYER_SOL))
+            .unwrap();
+        assert_eq!(quote.out_amount, 498_500_000);
+        assert_eq!(quote.fee_amount, 1_500_000);
+    }
+
+    #[test]
+    fn test_endo_avs_address_matches_the_map() {
+        for (avs_mint, endo_avs) in AVS_MINT_TO_ENDO_AVS_ADDRESS.iter() {
+            let amm = SolayerEndoAVSAmm::new(
+                POOL_KEY,
+                swap_state(SOLAYER_SOL, *avs_mint),
+                SOLAYER_DELEGATION_PROGRAM,
+                &amm_context(),
+            );
+            // the cached path for the pool's own mint, the map for every other one
+            for (other_mint, other_endo_avs) in AVS_MINT_TO_ENDO_AVS_ADDRESS.iter() {
+                assert_eq!(amm.endo_avs_address(other_mint), Ok(*other_endo_avs));
+            }
+            assert_eq!(amm.endo_avs_address(avs_mint), Ok(*endo_avs));
+        }
+        assert_eq!(
+            bybit_amm().endo_avs_address(&SOLAYER_SOL),
+            Err(SolayerAmmError::UnknownAvsMint(SOLAYER_SOL))
+        );
+    }
+
+    #[test]
+    fn test_swap_variant_follows_the_direction() {
+        let amm = bybit_amm();
+
+        let delegate = amm
+            .get_swap_and_account_metas(&swap_params(SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert!(matches!(delegate.swap, Swap::SolayerDelegateNoInit));
+        let undelegate = amm
+            .get_swap_and_account_metas(&swap_params(BYBIT_AVS_MINT, SOLAYER_SOL))
+            .unwrap();
+        assert!(matches!(undelegate.swap, Swap::SolayerUndelegateNoInit));
+    }
+
+    #[test]
+    fn test_unknown_avs_mint_is_an_error() {
+        let amm = bybit_amm();
+        let unknown_mint = Pubkey::new_unique();
+
+        for (source_mint, destination_mint) in [(SOLAYER_SOL, unknown_mint), (unknown_mint, SOLAYER_SOL)] {
+            let err = amm
+                .get_swap_and_account_metas(&swap_params(source_mint, destination_mint))
+                .err()
+                .unwrap();
+            assert_eq!(
+                err.downcast_ref::<SolayerAmmError>(),
+                Some(&SolayerAmmError::UnknownAvsMint(unknown_mint))
+            );
+        }
+    }
+
+    #[test]
+    fn test_metas_len_matches_get_accounts_len() {
+        let amm = bybit_amm();
+
+        for (source_mint, destination_mint) in [(SOLAYER_SOL, BYBIT_AVS_MINT), (BYBIT_AVS_MINT, SOLAYER_SOL)] {
+            let SwapAndAccountMetas { account_metas, .. } = amm
+                .get_swap_and_account_metas(&swap_params(source_mint, destination_mint))
+                .unwrap();
+            assert_eq!(account_metas.len(), amm.get_accounts_len());
+            assert_eq!(account_metas.len(), SWAP_ACCOUNTS_LEN);
+        }
+    }
+
+    #[test]
+    fn test_quote_rejects_pairs_without_exactly_one_ssol_side() {
+        let amm = bybit_amm();
+        let usdc = Pubkey::new_unique();
+
+        // neither side is sSOL
+        let err = amm.quote(&quote_params(1_000, usdc, BYBIT_AVS_MINT)).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::UnsupportedMintPair {
+                input_mint: usdc,
+                output_mint: BYBIT_AVS_MINT,
+            })
+        );
+        // both sides are AVS mints, only quoted when the builder enables AVS -> AVS
+        let err = amm
+            .quote(&quote_params(1_000, OKX_AVS_MINT, BYBIT_AVS_MINT))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::UnsupportedMintPair {
+                input_mint: OKX_AVS_MINT,
+                output_mint: BYBIT_AVS_MINT,
+            })
+        );
+    }
+
+    #[test]
+    fn test_update_loads_reserves_from_the_account_map() {
+        let mut amm = bybit_amm();
+        assert_eq!(amm.reserves(), [0, 0]);
+
+        load_reserves(&mut amm, [5_000, 7_000]);
+        assert_eq!(amm.reserves(), [5_000, 7_000]);
+
+        // a token account missing from a later update keeps its last balance
+        amm.update(&account_map([(TOKEN_A, token_account(SOLAYER_SOL, POOL_KEY, 6_000))]))
+            .unwrap();
+        assert_eq!(amm.reserves(), [6_000, 7_000]);
+    }
+
+    #[test]
+    fn test_avs_override_resolves_in_swap_metas() {
+        let custom_mint = Pubkey::new_unique();
+        let custom_endo_avs = Pubkey::new_unique();
+        let amm = test_builder()
+            .avs_overrides(HashMap::from([(custom_mint, custom_endo_avs)]))
+            .build_with_state(
+                POOL_KEY,
+                swap_state(SOLAYER_SOL, custom_mint),
+                SOLAYER_DELEGATION_PROGRAM,
+                &amm_context(),
+            );
+
+        let SwapAndAccountMetas { account_metas, .. } = amm
+            .get_swap_and_account_metas(&swap_params(SOLAYER_SOL, custom_mint))
+            .unwrap();
+        assert_eq!(account_metas[1].pubkey, custom_endo_avs);
+        // the built-in set is still there
+        assert_eq!(amm.endo_avs_address(&BYBIT_AVS_MINT), Ok(BYBIT_AVS_ADDRESS));
+    }
+
+    #[cfg(feature = "mainnet-avs")]
+    #[test]
+    fn test_is_supported_avs_mint() {
+        assert!(SolayerEndoAVSAmm::is_supported_avs_mint(&BYBIT_AVS_MINT));
+        assert!(!SolayerEndoAVSAmm::is_supported_avs_mint(&SOLAYER_SOL));
+        assert!(!SolayerEndoAVSAmm::is_supported_avs_mint(&Pubkey::new_unique()));
+
+        let mints = SolayerEndoAVSAmm::supported_avs_mints();
+        assert_eq!(mints.len(), SUPPORTED_AVS.len());
+        assert!(mints.contains(&BYBIT_AVS_MINT));
+        assert!(!mints.contains(&SOLAYER_SOL));
+    }
+
+    #[cfg(feature = "mainnet-avs")]
+    #[test]
+    fn test_avs_maps_round_trip_every_pair() {
+        assert_eq!(AVS_MINT_TO_ENDO_AVS_ADDRESS.len(), SUPPORTED_AVS.len());
+        assert_eq!(ENDO_AVS_ADDRESS_TO_AVS_MINT.len(), SUPPORTED_AVS.len());
+        for avs in SUPPORTED_AVS {
+            assert_eq!(AVS_MINT_TO_ENDO_AVS_ADDRESS[&avs.mint], avs.address);
+            assert_eq!(SolayerEndoAVSAmm::avs_mint_for_endo_avs(&avs.address), Some(avs.mint));
+        }
+        assert_eq!(SolayerEndoAVSAmm::avs_mint_for_endo_avs(&Pubkey::new_unique()), None);
+    }
+
+    #[test]
+    fn test_swap_mode_decides_which_side_is_the_amount() {
+        // a fee makes in and out differ, so the side holding the amount is visible
+        let amm = bybit_amm_with_fee_bps(30);
+
+        let exact_in = amm
+            .quote(&quote_params(1_000_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(exact_in.in_amount, 1_000_000);
+        assert_eq!(exact_in.out_amount, 997_000);
+
+        let exact_out = amm
+            .quote(&QuoteParams {
+                swap_mode: SwapMode::ExactOut,
+                ..quote_params(997_000, SOLAYER_SOL, BYBIT_AVS_MINT)
+            })
+            .unwrap();
+        assert_eq!(exact_out.out_amount, 997_000);
+        assert_eq!(exact_out.in_amount, 1_000_000);
+    }
+
+    #[test]
+    fn test_malformed_swap_account_data_is_an_error() {
+        let data = swap_account(&swap_state(SOLAYER_SOL, BYBIT_AVS_MINT), SOLAYER_DELEGATION_PROGRAM).data;
+        let mut wrong_version = data.clone();
+        wrong_version[0] = 9;
+
+        assert_eq!(
+            unpack_swap_state(&POOL_KEY, &[]),
+            Err(SolayerAmmError::EmptyAccountData)
+        );
+        assert_eq!(
+            unpack_swap_state(&POOL_KEY, &data[..100]),
+            Err(SolayerAmmError::AccountDataTooShort {
+                expected: 1 + SwapV1::LEN,
+                actual: 100,
+            })
+        );
+        assert_eq!(
+            unpack_swap_state(&POOL_KEY, &wrong_version),
+            Err(SolayerAmmError::UnsupportedVersion {
+                version: 9,
+                expected: SWAP_V1_VERSION,
+            })
+        );
+        // the same errors come out of the constructors instead of a panic
+        for data in [&[][..], &data[..100], &wrong_version] {
+            let err = SolayerEndoAVSAmm::from_account_data(POOL_KEY, SOLAYER_DELEGATION_PROGRAM, data, &amm_context())
+                .unwrap_err();
+            assert!(err.downcast_ref::<SolayerAmmError>().is_some());
+        }
+    }
+
+    #[test]
+    fn test_clone_keeps_every_field() {
+        let mut amm = bybit_amm_with(test_builder().avs_to_avs(true).activation_epoch(3).min_amount(10));
+        load_reserves(&mut amm, [5_000, 7_000]);
+        amm.set_direction_enabled(SwapDirection::Undelegate, false);
+
+        let clone = amm.clone();
+        assert_eq!(clone.key, amm.key);
+        assert_eq!(clone.label, amm.label);
+        assert_eq!(clone.state, amm.state);
+        assert_eq!(clone.reserve_mints, amm.reserve_mints);
+        assert_eq!(clone.reserves, amm.reserves);
+        assert_eq!(clone.reserves_loaded, amm.reserves_loaded);
+        assert_eq!(clone.avs_addresses, amm.avs_addresses);
+        assert_eq!(clone.endo_avs, amm.endo_avs);
+        assert_eq!(clone.undelegate_enabled, amm.undelegate_enabled);
+        assert_eq!(clone.avs_to_avs, amm.avs_to_avs);
+        assert_eq!(clone.activation_epoch, amm.activation_epoch);
+        assert_eq!(clone.min_amount, amm.min_amount);
+        // the boxed clone routers use is the same pool
+        assert_eq!(amm.clone_amm().key(), amm.key());
+    }
+
+    #[test]
+    fn test_instruction_encodes_a_partial_amount() {
+        let amm = bybit_amm();
+        // a quarter of the staker's position, the rest stays put
+        let swap_params = SwapParams {
+            in_amount: 250_000_000,
+            ..swap_params(SOLAYER_SOL, BYBIT_AVS_MINT)
+        };
+
+        let instruction = amm.build_instruction(&swap_params).unwrap();
+        assert_eq!(instruction.data, instruction::delegate_data(250_000_000));
+        assert_eq!(instruction.data[8..], 250_000_000u64.to_le_bytes());
+
+        let zero = SwapParams {
+            in_amount: 0,
+            ..swap_params
+        };
+        let err = amm.build_instruction(&zero).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::ZeroAmount)
+        );
+    }
+
+    // sSOL / Bybit pool charging `numerator / denominator` of the input
+    fn bybit_amm_with_trade_fee(numerator: u64, denominator: u64) -> SolayerEndoAVSAmm {
+        let mut state = swap_state(SOLAYER_SOL, BYBIT_AVS_MINT);
+        state.fees.trade_fee_numerator = numerator;
+        state.fees.trade_fee_denominator = denominator;
+        test_builder().build_with_state(POOL_KEY, state, SOLAYER_DELEGATION_PROGRAM, &amm_context())
+    }
+
+    #[test]
+    fn test_trade_fee_reduces_the_output() {
+        let amm = bybit_amm_with_trade_fee(25, 10_000);
+
+        let quote = amm
+            .quote(&quote_params(1_000_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(quote.out_amount, 997_500);
+        assert_eq!(quote.fee_amount, 2_500);
+        assert_eq!(quote.fee_pct, Decimal::new(25, 4));
+
+        // no fee configured, the output is the input
+        let quote = bybit_amm()
+            .quote(&quote_params(1_000_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(quote.out_amount, 1_000_000);
+        assert_eq!(quote.fee_amount, 0);
+    }
+
+    #[test]
+    fn test_endo_avs_is_an_account_to_update() {
+        let amm = bybit_amm();
+
+        let accounts = amm.get_accounts_to_update();
+        assert_eq!(accounts[..2], [TOKEN_A, TOKEN_B]);
+        assert!(accounts.contains(&BYBIT_AVS_ADDRESS));
+    }
+
+    #[test]
+    fn test_program_dependencies_match_the_swap_programs() {
+        let known_programs = [SOLAYER_DELEGATION_PROGRAM, spl_token::id(), spl_token_2022::id()];
+        for amm in [
+            bybit_amm(),
+            bybit_amm_with(test_builder().token_programs(HashMap::from([(BYBIT_AVS_MINT, spl_token_2022::id())]))),
+        ] {
+            let dependencies: HashSet<Pubkey> = amm
+                .program_dependencies()
+                .into_iter()
+                .map(|(program_id, _)| program_id)
+                .collect();
+            for (source_mint, destination_mint) in [(SOLAYER_SOL, BYBIT_AVS_MINT), (BYBIT_AVS_MINT, SOLAYER_SOL)] {
+                let instruction = amm
+                    .build_instruction(&swap_params(source_mint, destination_mint))
+                    .unwrap();
+                let mut programs: HashSet<Pubkey> = instruction
+                    .accounts
+                    .iter()
+                    .map(|account_meta| account_meta.pubkey)
+                    .filter(|pubkey| known_programs.contains(pubkey))
+                    .collect();
+                programs.insert(instruction.program_id);
+                assert_eq!(dependencies, programs);
+            }
+        }
+    }
+
+    #[test]
+    fn test_new_matches_building_from_account_data() {
+        let state = swap_state(BYBIT_AVS_MINT, SOLAYER_SOL);
+        let data = swap_account(&state, SOLAYER_DELEGATION_PROGRAM).data;
+
+        let amm = SolayerEndoAVSAmm::new(POOL_KEY, state, SOLAYER_DELEGATION_PROGRAM, &amm_context());
+        let from_data =
+            SolayerEndoAVSAmm::from_account_data(POOL_KEY, SOLAYER_DELEGATION_PROGRAM, &data, &amm_context()).unwrap();
+
+        // reserve mints come from the state, in storage order
+        assert_eq!(amm.get_reserve_mints(), vec![BYBIT_AVS_MINT, SOLAYER_SOL]);
+        assert_eq!(amm.get_reserve_mints(), from_data.get_reserve_mints());
+        assert_eq!(amm.key(), from_data.key());
+        assert_eq!(amm.label(), from_data.label());
+        assert_eq!(amm.get_accounts_to_update(), from_data.get_accounts_to_update());
+    }
+
+    #[test]
+    fn test_swapped_token_accounts_are_rejected() {
+        let swap_params = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+        let staker = swap_params.token_transfer_authority;
+        // the AVS account passed as the source and the sSOL account as the destination
+        let swapped = account_map([
+            (
+                swap_params.source_token_account,
+                token_account(BYBIT_AVS_MINT, staker, 1_000),
+            ),
+            (
+                swap_params.destination_token_account,
+                token_account(SOLAYER_SOL, staker, 0),
+            ),
+        ]);
+
+        assert_eq!(
+            SolayerEndoAVSAmm::validate_swap_token_accounts(&swap_params, &swapped),
+            Err(SolayerAmmError::TokenAccountMintMismatch {
+                side: "source",
+                address: swap_params.source_token_account,
+                mint: BYBIT_AVS_MINT,
+                expected_mint: SOLAYER_SOL,
+            })
+        );
+        let matching = account_map([
+            (
+                swap_params.source_token_account,
+                token_account(SOLAYER_SOL, staker, 1_000),
+            ),
+            (
+                swap_params.destination_token_account,
+                token_account(BYBIT_AVS_MINT, staker, 0),
+            ),
+        ]);
+        assert_eq!(
+            SolayerEndoAVSAmm::validate_swap_token_accounts(&swap_params, &matching),
+            Ok(())
+        );
+        // accounts absent from the map aren't checked
+        assert_eq!(
+            SolayerEndoAVSAmm::validate_swap_token_accounts(&swap_params, &account_map([])),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_label_names_the_avs() {
+        assert_eq!(bybit_amm().label(), "Solayer (Bybit AVS)");
+
+        // an AVS without a name falls back to its mint
+        let custom_mint = Pubkey::new_unique();
+        let amm = test_builder()
+            .avs_overrides(HashMap::from([(custom_mint, Pubkey::new_unique())]))
+            .build_with_state(
+                POOL_KEY,
+                swap_state(SOLAYER_SOL, custom_mint),
+                SOLAYER_DELEGATION_PROGRAM,
+                &amm_context(),
+            );
+        assert!(amm.label().contains(&custom_mint.to_string()));
+    }
+
+    #[test]
+    fn test_sol_and_avs_mint_ignore_storage_order() {
+        for state in [
+            swap_state(SOLAYER_SOL, BYBIT_AVS_MINT),
+            swap_state(BYBIT_AVS_MINT, SOLAYER_SOL),
+        ] {
+            let amm = SolayerEndoAVSAmm::new(POOL_KEY, state, SOLAYER_DELEGATION_PROGRAM, &amm_context());
+            assert_eq!(amm.sol_mint(), SOLAYER_SOL);
+            assert_eq!(amm.avs_mint(), BYBIT_AVS_MINT);
+        }
+    }
+
+    // Records the name of every span opened while it is the default subscriber
+    #[derive(Clone, Default)]
+    struct SpanRecorder(Arc<std::sync::Mutex<Vec<&'static str>>>);
+
+    impl tracing::Subscriber for SpanRecorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut names = self.0.lock().unwrap();
+            names.push(span.metadata().name());
+            tracing::span::Id::from_u64(names.len() as u64)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, _: &tracing::Event<'_>) {}
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[test]
+    fn test_quote_and_swap_open_debug_spans() {
+        let amm = bybit_amm();
+        let recorder = SpanRecorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            amm.quote(&quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT)).unwrap();
+            amm.get_swap_and_account_metas(&swap_params(SOLAYER_SOL, BYBIT_AVS_MINT))
+                .unwrap();
+        });
+        let names = recorder.0.lock().unwrap();
+        assert!(names.contains(&"quote"), "{names:?}");
+        assert!(names.contains(&"get_swap_and_account_metas"), "{names:?}");
+    }
+
+    #[test]
+    fn test_both_directions_put_each_role_in_place() {
+        let amm = bybit_amm();
+        let endo_avs = amm.endo_avs_address(&BYBIT_AVS_MINT).unwrap();
+        let delegated_token_vault = amm.derive_delegated_token_vault(&endo_avs);
+        for (source_mint, destination_mint) in [(SOLAYER_SOL, BYBIT_AVS_MINT), (BYBIT_AVS_MINT, SOLAYER_SOL)] {
+            let swap_params = swap_params(source_mint, destination_mint);
+            let (staker_ssol, staker_avs) = if source_mint == SOLAYER_SOL {
+                (swap_params.source_token_account, swap_params.destination_token_account)
+            } else {
+                (swap_params.destination_token_account, swap_params.source_token_account)
+            };
+            let SwapAndAccountMetas { account_metas, .. } = amm.get_swap_and_account_metas(&swap_params).unwrap();
+            let expected = [
+                swap_params.token_transfer_authority,
+                endo_avs,
+                BYBIT_AVS_MINT,
+                delegated_token_vault,
+                SOLAYER_SOL,
+                staker_ssol,
+                staker_avs,
+                spl_token::id(),
+            ];
+            let keys: Vec<Pubkey> = account_metas.iter().map(|meta| meta.pubkey).collect();
+            assert_eq!(keys, expected);
+            let writable: Vec<bool> = account_metas.iter().map(|meta| meta.is_writable).collect();
+            assert_eq!(writable, SWAP_ACCOUNTS_WRITABLE);
+            assert!(account_metas[0].is_signer);
+            assert!(account_metas[1..].iter().all(|meta| !meta.is_signer));
+        }
+    }
+
+    #[test]
+    fn test_estimated_compute_units_per_direction() {
+        let amm = bybit_amm();
+        let delegate = amm.estimated_compute_units(&swap_params(SOLAYER_SOL, BYBIT_AVS_MINT));
+        let undelegate = amm.estimated_compute_units(&swap_params(BYBIT_AVS_MINT, SOLAYER_SOL));
+        assert_eq!(delegate, DELEGATE_COMPUTE_UNITS);
+        assert_eq!(undelegate, UNDELEGATE_COMPUTE_UNITS);
+        // well above a bare transfer, well below the 200k default instruction budget
+        for compute_units in [delegate, undelegate] {
+            assert!((10_000..200_000).contains(&compute_units), "{compute_units}");
+        }
+        // undelegating also signs for the vault with the endoAVS PDA
+        assert!(undelegate > delegate);
+    }
+
+    #[test]
+    fn test_quote_refuses_a_zero_amount() {
+        let amm = bybit_amm();
+        for (input_mint, output_mint) in [(SOLAYER_SOL, BYBIT_AVS_MINT), (BYBIT_AVS_MINT, SOLAYER_SOL)] {
+            let err = amm.quote(&quote_params(0, input_mint, output_mint)).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<SolayerAmmError>(),
+                Some(&SolayerAmmError::ZeroAmount)
+            );
+        }
+    }
+
+    #[test]
+    fn test_identical_mints_are_rejected() {
+        let amm = bybit_amm();
+        for mint in [SOLAYER_SOL, BYBIT_AVS_MINT] {
+            let err = amm.quote(&quote_params(1_000, mint, mint)).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<SolayerAmmError>(),
+                Some(&SolayerAmmError::SameMint(mint))
+            );
+
+            let err = amm.get_swap_and_account_metas(&swap_params(mint, mint)).err().unwrap();
+            assert_eq!(
+                err.downcast_ref::<SolayerAmmError>(),
+                Some(&SolayerAmmError::SameMint(mint))
+            );
+        }
+    }
+
+    #[test]
+    fn test_state_exposes_the_pool_parameters() {
+        let pool_mint = Pubkey::new_unique();
+        let pool_fee_account = Pubkey::new_unique();
+        let amm = test_builder().build_with_state(
+            POOL_KEY,
+            SwapV1 {
+                pool_mint: swap_pubkey(pool_mint),
+                pool_fee_account: swap_pubkey(pool_fee_account),
+                bump_seed: 254,
+                ..swap_state(SOLAYER_SOL, BYBIT_AVS_MINT)
+            },
+            SOLAYER_DELEGATION_PROGRAM,
+            &amm_context(),
+        );
+
+        let SwapState::V1(state) = amm.state();
+        assert_eq!(state.pool_mint, swap_pubkey(pool_mint));
+        assert_eq!(state.bump_seed, 254);
+        assert_eq!(amm.state().version(), SWAP_V1_VERSION);
+        assert_eq!(amm.state().token_a_mint(), SOLAYER_SOL);
+        assert_eq!(amm.state().token_b_mint(), BYBIT_AVS_MINT);
+        assert_eq!(amm.pool_fee_account(), pool_fee_account);
+    }
+
+    #[test]
+    fn test_update_hints_follow_the_fetched_state() {
+        let mut amm = bybit_amm();
+        assert!(amm.has_dynamic_accounts());
+        assert!(amm.requires_update_for_reserve_mints());
+        assert_eq!(amm.get_reserve_mints(), [SOLAYER_SOL, BYBIT_AVS_MINT]);
+
+        amm.update(&account_map([
+            (SOLAYER_SOL, mint_account(9, 0, spl_token::id())),
+            (BYBIT_AVS_MINT, mint_account(9, 0, spl_token::id())),
+        ]))
+        .unwrap();
+        load_reserves(&mut amm, [5, 7]);
+        assert!(!amm.requires_update_for_reserve_mints());
+
+        // watching an ATA changes the accounts to update
+        let accounts_to_update = amm.get_accounts_to_update();
+        let ata = Pubkey::new_unique();
+        amm.watch_destination_ata(ata);
+        assert!(amm.has_dynamic_accounts());
+        assert_eq!(amm.get_accounts_to_update(), [accounts_to_update, vec![ata]].concat());
+    }
+
+    #[test]
+    fn test_quote_populates_every_field() {
+        let quote = bybit_amm()
+            .quote(&quote_params(1_000_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(quote.min_in_amount, Some(1_000_000));
+        assert_eq!(quote.min_out_amount, Some(1_000_000));
+        assert_eq!(
+            quote_fields(&quote),
+            (1_000_000, 1_000_000, 0, SOLAYER_SOL, Decimal::ZERO)
+        );
+
+        let quote = bybit_amm_with_trade_fee(25, 10_000)
+            .quote(&quote_params(1_000_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        // 1:1 less fees, so the minimums are the amounts themselves
+        assert_eq!(quote.min_in_amount, Some(1_000_000));
+        assert_eq!(quote.min_out_amount, Some(997_500));
+        assert_eq!(
+            quote_fields(&quote),
+            (1_000_000, 997_500, 2_500, SOLAYER_SOL, Decimal::new(25, 4))
+        );
+    }
+
+    #[cfg(not(feature = "mainnet-avs"))]
+    #[test]
+    fn test_no_built_in_avs_without_mainnet_avs() {
+        assert!(AVS_MINT_TO_ENDO_AVS_ADDRESS.is_empty());
+        assert!(ENDO_AVS_ADDRESS_TO_AVS_MINT.is_empty());
+        assert!(!SolayerEndoAVSAmm::is_supported_avs_mint(&BYBIT_AVS_MINT));
+
+        // only mappings registered through the builder resolve
+        let amm = bybit_amm_with(SolayerEndoAVSAmmBuilder::new());
+        assert_eq!(
+            amm.endo_avs_address(&BYBIT_AVS_MINT),
+            Err(SolayerAmmError::UnknownAvsMint(BYBIT_AVS_MINT))
+        );
+        let amm = bybit_amm_with(test_builder());
+        assert_eq!(amm.endo_avs_address(&BYBIT_AVS_MINT), Ok(BYBIT_AVS_ADDRESS));
+    }
+
+    #[test]
+    fn test_default_token_accounts_are_rejected() {
+        let amm = bybit_amm();
+        for (source_mint, destination_mint) in [(SOLAYER_SOL, BYBIT_AVS_MINT), (BYBIT_AVS_MINT, SOLAYER_SOL)] {
+            let missing_source = SwapParams {
+                source_token_account: Pubkey::default(),
+                ..swap_params(source_mint, destination_mint)
+            };
+            let err = amm.get_swap_and_account_metas(&missing_source).err().unwrap();
+            assert_eq!(
+                err.downcast_ref::<SolayerAmmError>(),
+                Some(&SolayerAmmError::MissingTokenAccount {
+                    side: "source",
+                    mint: source_mint,
+                })
+            );
+
+            let missing_destination = SwapParams {
+                destination_token_account: Pubkey::default(),
+                ..swap_params(source_mint, destination_mint)
+            };
+            let err = amm.get_swap_and_account_metas(&missing_destination).err().unwrap();
+            assert_eq!(
+                err.downcast_ref::<SolayerAmmError>(),
+                Some(&SolayerAmmError::MissingTokenAccount {
+                    side: "destination",
+                    mint: destination_mint,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_expected_destination_ata_matches_the_associated_token_address() {
+        let owner = Pubkey::new_unique();
+        let amm = bybit_amm();
+        for mint in [SOLAYER_SOL, BYBIT_AVS_MINT] {
+            assert_eq!(
+                amm.expected_destination_ata(&owner, &mint),
+                spl_associated_token_account::get_associated_token_address(&owner, &mint)
+            );
+        }
+
+        // a Token-2022 AVS mint derives under Token-2022
+        let amm =
+            bybit_amm_with(test_builder().token_programs(HashMap::from([(BYBIT_AVS_MINT, spl_token_2022::id())])));
+        let ata = amm.expected_destination_ata(&owner, &BYBIT_AVS_MINT);
+        assert_ne!(
+            ata,
+            spl_associated_token_account::get_associated_token_address(&owner, &BYBIT_AVS_MINT)
+        );
+        assert_eq!(
+            ata,
+            get_associated_token_address_with_program_id(&owner, &BYBIT_AVS_MINT, &spl_token_2022::id())
+        );
+    }
+
+    #[test]
+    fn test_quote_refuses_an_empty_output_reserve() {
+        // before any update the reserves are unknown, not empty
+        let mut amm = bybit_amm();
+        assert!(amm.quote(&quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT)).is_ok());
+
+        // no AVS tokens left to hand out, sSOL can still come back
+        load_reserves(&mut amm, [1_000_000, 0]);
+        let err = amm
+            .quote(&quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::NoLiquidity)
+        );
+        assert!(amm.quote(&quote_params(1_000, BYBIT_AVS_MINT, SOLAYER_SOL)).is_ok());
+
+        load_reserves(&mut amm, [0, 1_000_000]);
+        let err = amm
+            .quote(&quote_params(1_000, BYBIT_AVS_MINT, SOLAYER_SOL))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::NoLiquidity)
+        );
+    }
+
+    #[cfg(feature = "mainnet-avs")]
+    #[test]
+    fn test_derived_endo_avs_address_matches_the_map() {
+        assert_eq!(
+            derive_endo_avs_address(&BYBIT_AVS_MINT, &SOLAYER_DELEGATION_PROGRAM),
+            BYBIT_AVS_ADDRESS
+        );
+        for avs in SUPPORTED_AVS {
+            assert_eq!(
+                verify_endo_avs_address(&avs.mint, &SOLAYER_DELEGATION_PROGRAM),
+                Ok(()),
+                "{}",
+                avs.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_accounts_len_counts_a_missing_destination_ata() {
+        let owner = Pubkey::new_unique();
+        let mut amm = bybit_amm();
+        let ata = amm.expected_destination_ata(&owner, &BYBIT_AVS_MINT);
+        amm.watch_destination_ata(ata);
+        assert!(amm.get_accounts_to_update().contains(&ata));
+        // unknown until an update has looked for it
+        assert_eq!(amm.get_accounts_len(), SWAP_ACCOUNTS_LEN);
+
+        amm.update(&account_map([])).unwrap();
+        assert_eq!(amm.get_accounts_len(), SWAP_ACCOUNTS_LEN + ATA_CREATION_ACCOUNTS_LEN);
+
+        amm.update(&account_map([(ata, token_account(BYBIT_AVS_MINT, owner, 0))]))
+            .unwrap();
+        assert_eq!(amm.get_accounts_len(), SWAP_ACCOUNTS_LEN);
+    }
+
+    #[cfg(feature = "mainnet-avs")]
+    #[test]
+    fn test_supported_avs_yields_every_built_in_avs() {
+        let supported_avs: Vec<SupportedAvs> = SolayerEndoAVSAmm::supported_avs().collect();
+        assert_eq!(supported_avs.len(), 8);
+        assert!(supported_avs.contains(&SupportedAvs {
+            mint: BYBIT_AVS_MINT,
+            address: BYBIT_AVS_ADDRESS,
+            name: "Bybit",
+        }));
+        assert!(supported_avs.contains(&SupportedAvs {
+            mint: ALT_LAYER_AVS_MINT,
+            address: ALT_LAYER_AVS_ADDRESS,
+            name: "AltLayer",
+        }));
+        for avs in &supported_avs {
+            assert_eq!(AVS_MINT_TO_ENDO_AVS_ADDRESS.get(&avs.mint), Some(&avs.address));
+        }
+    }
+
+    #[test]
+    fn test_disabling_undelegation_only_blocks_that_direction() {
+        let mut amm = bybit_amm();
+        assert!(amm.can_quote(&SOLAYER_SOL, &BYBIT_AVS_MINT));
+        assert!(amm.can_quote(&BYBIT_AVS_MINT, &SOLAYER_SOL));
+
+        amm.set_direction_enabled(SwapDirection::Undelegate, false);
+        assert!(amm.can_quote(&SOLAYER_SOL, &BYBIT_AVS_MINT));
+        assert!(!amm.can_quote(&BYBIT_AVS_MINT, &SOLAYER_SOL));
+        assert!(amm.quote(&quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT)).is_ok());
+        let err = amm
+            .quote(&quote_params(1_000, BYBIT_AVS_MINT, SOLAYER_SOL))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::DirectionDisabled(SwapDirection::Undelegate))
+        );
+        assert!(amm
+            .get_swap_and_account_metas(&swap_params(BYBIT_AVS_MINT, SOLAYER_SOL))
+            .is_err());
+
+        amm.set_direction_enabled(SwapDirection::Undelegate, true);
+        assert!(amm.quote(&quote_params(1_000, BYBIT_AVS_MINT, SOLAYER_SOL)).is_ok());
+    }
+
+    #[test]
+    fn test_errors_can_be_matched_by_kind() {
+        let amm = bybit_amm();
+        let unknown_mint = Pubkey::new_unique();
+        let err = amm.quote(&quote_params(1_000, SOLAYER_SOL, unknown_mint)).unwrap_err();
+        match err.downcast_ref::<SolayerAmmError>() {
+            Some(SolayerAmmError::UnknownAvsMint(mint)) => assert_eq!(*mint, unknown_mint),
+            other => panic!("expected UnknownAvsMint, got {other:?}"),
+        }
+
+        // unpacking returns the typed error directly
+        assert_eq!(
+            SolayerEndoAVSAmm::from_account_data(POOL_KEY, SOLAYER_DELEGATION_PROGRAM, &[], &amm_context())
+                .err()
+                .unwrap()
+                .downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::EmptyAccountData)
+        );
+        assert_eq!(
+            unpack_swap_state(&POOL_KEY, &[]),
+            Err(SolayerAmmError::EmptyAccountData)
+        );
+    }
+
+    #[test]
+    fn test_avs_to_avs_produces_both_legs() {
+        let swap_params = swap_params(BYBIT_AVS_MINT, OKX_AVS_MINT);
+        // off by default
+        assert!(bybit_amm()
+            .quote(&quote_params(1_000_000, BYBIT_AVS_MINT, OKX_AVS_MINT))
+            .is_err());
+        assert!(bybit_amm().get_avs_to_avs_swap_legs(&swap_params).is_err());
+
+        let amm = bybit_amm_with(test_builder().avs_to_avs(true));
+        let quote = amm
+            .quote(&quote_params(1_000_000, BYBIT_AVS_MINT, OKX_AVS_MINT))
+            .unwrap();
+        assert_eq!((quote.in_amount, quote.out_amount), (1_000_000, 1_000_000));
+
+        let [undelegate, delegate] = amm.get_avs_to_avs_swap_legs(&swap_params).unwrap();
+        assert_eq!(undelegate.swap, Swap::SolayerUndelegateNoInit);
+        assert_eq!(delegate.swap, Swap::SolayerDelegateNoInit);
+        assert_eq!(
+            undelegate.account_metas.len() + delegate.account_metas.len(),
+            2 * SWAP_ACCOUNTS_LEN
+        );
+
+        // out of Bybit into the staker's sSOL ATA, then from there into OKX
+        let sol_token_account = get_associated_token_address_with_program_id(
+            &swap_params.token_transfer_authority,
+            &SOLAYER_SOL,
+            &spl_token::id(),
+        );
+        assert_eq!(undelegate.account_metas[1].pubkey, BYBIT_AVS_ADDRESS);
+        assert_eq!(undelegate.account_metas[2].pubkey, BYBIT_AVS_MINT);
+        assert_eq!(undelegate.account_metas[5].pubkey, sol_token_account);
+        assert_eq!(undelegate.account_metas[6].pubkey, swap_params.source_token_account);
+        assert_eq!(delegate.account_metas[1].pubkey, OKX_AVS_ADDRESS);
+        assert_eq!(delegate.account_metas[2].pubkey, OKX_AVS_MINT);
+        assert_eq!(delegate.account_metas[5].pubkey, sol_token_account);
+        assert_eq!(delegate.account_metas[6].pubkey, swap_params.destination_token_account);
+    }
+
+    #[test]
+    fn test_token_2022_destination_ata_creation() {
+        let payer = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let amm =
+            bybit_amm_with(test_builder().token_programs(HashMap::from([(BYBIT_AVS_MINT, spl_token_2022::id())])));
+        let ata = amm.expected_destination_ata(&owner, &BYBIT_AVS_MINT);
+        assert_eq!(
+            ata,
+            get_associated_token_address_with_program_id(&owner, &BYBIT_AVS_MINT, &spl_token_2022::id())
+        );
+
+        let instruction = amm.create_destination_ata_instruction(&payer, &owner, &BYBIT_AVS_MINT);
+        assert_eq!(instruction.program_id, spl_associated_token_account::id());
+        let keys: Vec<Pubkey> = instruction.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(
+            keys,
+            [
+                payer,
+                ata,
+                owner,
+                BYBIT_AVS_MINT,
+                solana_sdk::system_program::id(),
+                spl_token_2022::id()
+            ]
+        );
+
+        // the swap then delivers into that ATA under the same token program
+        let swap_params = SwapParams {
+            destination_token_account: ata,
+            token_transfer_authority: owner,
+            ..swap_params(SOLAYER_SOL, BYBIT_AVS_MINT)
+        };
+        let SwapAndAccountMetas { account_metas, .. } = amm.get_swap_and_account_metas(&swap_params).unwrap();
+        assert_eq!(account_metas[6].pubkey, ata);
+        assert_eq!(account_metas[SWAP_ACCOUNTS_LEN - 1].pubkey, spl_token_2022::id());
+    }
+
+    #[test]
+    fn test_build_instruction_targets_the_delegation_program() {
+        let amm = bybit_amm();
+        for (source_mint, destination_mint, discriminator) in [
+            (SOLAYER_SOL, BYBIT_AVS_MINT, instruction::DELEGATE_DISCRIMINATOR),
+            (BYBIT_AVS_MINT, SOLAYER_SOL, instruction::UNDELEGATE_DISCRIMINATOR),
+        ] {
+            let swap_params = swap_params(source_mint, destination_mint);
+            let instruction = amm.build_instruction(&swap_params).unwrap();
+            assert_eq!(instruction.program_id, SOLAYER_DELEGATION_PROGRAM);
+            assert_eq!(instruction.data[..8], discriminator);
+            assert_eq!(instruction.data[8..], swap_params.in_amount.to_le_bytes());
+
+            let SwapAndAccountMetas { account_metas, .. } = amm.get_swap_and_account_metas(&swap_params).unwrap();
+            assert_eq!(instruction.accounts, account_metas);
+        }
+    }
+
+    #[test]
+    fn test_normalized_reserve_mints_put_ssol_first() {
+        for (state, reserves) in [
+            (swap_state(SOLAYER_SOL, BYBIT_AVS_MINT), [5, 7]),
+            (swap_state(BYBIT_AVS_MINT, SOLAYER_SOL), [7, 5]),
+        ] {
+            let mut amm = SolayerEndoAVSAmm::new(POOL_KEY, state, SOLAYER_DELEGATION_PROGRAM, &amm_context());
+            load_reserves(&mut amm, reserves);
+            assert_eq!(amm.normalized_reserve_mints(), [SOLAYER_SOL, BYBIT_AVS_MINT]);
+            // sSOL's reserve follows it to the front
+            assert_eq!(amm.normalized_reserves(), [5, 7]);
+        }
+        // get_reserve_mints keeps the storage order
+        let amm = SolayerEndoAVSAmm::new(
+            POOL_KEY,
+            swap_state(BYBIT_AVS_MINT, SOLAYER_SOL),
+            SOLAYER_DELEGATION_PROGRAM,
+            &amm_context(),
+        );
+        assert_eq!(amm.get_reserve_mints(), [BYBIT_AVS_MINT, SOLAYER_SOL]);
+    }
+
+    #[test]
+    fn test_label_prefix_composes_with_the_avs_name() {
+        let amm = bybit_amm_with(test_builder().label_prefix("MyDEX-"));
+        assert_eq!(amm.label(), "MyDEX-Solayer (Bybit AVS)");
+        // unset, the label is unchanged
+        assert_eq!(
+            bybit_amm_with(test_builder().label_prefix("")).label(),
+            bybit_amm().label()
+        );
+    }
+
+    #[test]
+    fn test_exact_out_near_u64_max_overflows_cleanly() {
+        let amm = bybit_amm_with_trade_fee(25, 10_000);
+        let exact_out = |amount| QuoteParams {
+            swap_mode: SwapMode::ExactOut,
+            ..quote_params(amount, SOLAYER_SOL, BYBIT_AVS_MINT)
+        };
+
+        // the input covering the fee would exceed u64::MAX
+        let err = amm.quote(&exact_out(u64::MAX - 1)).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::MathOverflow("amount before trading fee"))
+        );
+
+        // with room left for the fee it still quotes
+        let quote = amm.quote(&exact_out(u64::MAX / 2)).unwrap();
+        assert_eq!(quote.out_amount, u64::MAX / 2);
+        assert!(quote.in_amount > quote.out_amount);
+
+        // and without a fee u64::MAX goes through 1:1
+        let quote = bybit_amm().quote(&exact_out(u64::MAX)).unwrap();
+        assert_eq!((quote.in_amount, quote.out_amount), (u64::MAX, u64::MAX));
+    }
+
+    #[test]
+    fn test_writable_and_readonly_accounts() {
+        let amm = bybit_amm();
+        let swap_params = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+        let endo_avs = amm.endo_avs_address(&BYBIT_AVS_MINT).unwrap();
+
+        // the staker's token accounts move funds, the staker itself only signs and the endoAVS
+        // is only read
+        let writable = amm.writable_accounts(&swap_params).unwrap();
+        assert_eq!(
+            writable,
+            [
+                BYBIT_AVS_MINT,
+                amm.derive_delegated_token_vault(&endo_avs),
+                swap_params.source_token_account,
+                swap_params.destination_token_account,
+            ]
+        );
+        let readonly = amm.readonly_accounts(&swap_params).unwrap();
+        assert_eq!(
+            readonly,
+            [
+                swap_params.token_transfer_authority,
+                endo_avs,
+                SOLAYER_SOL,
+                spl_token::id()
+            ]
+        );
+        assert_eq!(writable.len() + readonly.len(), SWAP_ACCOUNTS_LEN);
+    }
+
+    #[test]
+    fn test_wrong_owner_is_rejected() {
+        let wrong_owner = Pubkey::new_unique();
+        let data = swap_account(&swap_state(SOLAYER_SOL, BYBIT_AVS_MINT), wrong_owner).data;
+        let err = SolayerEndoAVSAmm::from_account_data(POOL_KEY, wrong_owner, &data, &amm_context())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::UnexpectedOwner {
+                account: POOL_KEY,
+                owner: wrong_owner,
+                expected: SOLAYER_DELEGATION_PROGRAM,
+            })
+        );
+
+        // a devnet deployment is accepted once named through the builder
+        let amm = test_builder()
+            .program_id(wrong_owner)
+            .build_from_account_data(POOL_KEY, wrong_owner, &data, &amm_context())
+            .unwrap();
+        assert_eq!(amm.program_id(), wrong_owner);
+    }
+
+    #[test]
+    fn test_metas_into_a_reused_buffer_match_the_allocating_path() {
+        let amm = bybit_amm();
+        let mut buf = Vec::new();
+        for (source_mint, destination_mint) in [(SOLAYER_SOL, BYBIT_AVS_MINT), (BYBIT_AVS_MINT, SOLAYER_SOL)] {
+            let swap_params = swap_params(source_mint, destination_mint);
+            let swap = amm.get_swap_and_account_metas_into(&swap_params, &mut buf).unwrap();
+            let SwapAndAccountMetas {
+                swap: expected_swap,
+                account_metas,
+            } = amm.get_swap_and_account_metas(&swap_params).unwrap();
+            assert_eq!(swap, expected_swap);
+            // the previous swap's metas are cleared rather than appended to
+            assert_eq!(buf, account_metas);
+        }
+        assert!(buf.capacity() >= SWAP_ACCOUNTS_LEN);
+    }
+
+    #[test]
+    fn test_delegation_program_constant_drives_owner_validation() {
+        assert_ne!(SOLAYER_DELEGATION_PROGRAM, Pubkey::default());
+
+        // an account owned by the constant is accepted, the same bytes under another owner aren't
+        let data = swap_account(&swap_state(SOLAYER_SOL, BYBIT_AVS_MINT), SOLAYER_DELEGATION_PROGRAM).data;
+        let amm =
+            SolayerEndoAVSAmm::from_account_data(POOL_KEY, SOLAYER_DELEGATION_PROGRAM, &data, &amm_context()).unwrap();
+        assert_eq!(amm.program_id(), SOLAYER_DELEGATION_PROGRAM);
+        assert!(SolayerEndoAVSAmm::from_account_data(POOL_KEY, Pubkey::new_unique(), &data, &amm_context()).is_err());
+    }
+
+    #[test]
+    fn test_quote_with_pool_returns_the_endo_avs() {
+        let amm = bybit_amm();
+        for (input_mint, output_mint) in [(SOLAYER_SOL, BYBIT_AVS_MINT), (BYBIT_AVS_MINT, SOLAYER_SOL)] {
+            let (quote, endo_avs) = amm
+                .quote_with_pool(&quote_params(1_000, input_mint, output_mint))
+                .unwrap();
+            assert_eq!(endo_avs, BYBIT_AVS_ADDRESS);
+            assert_eq!(
+                quote_fields(&quote),
+                quote_fields(&amm.quote(&quote_params(1_000, input_mint, output_mint)).unwrap())
+            );
+        }
+        #[cfg(feature = "mainnet-avs")]
+        assert_eq!(
+            AVS_MINT_TO_ENDO_AVS_ADDRESS.get(&BYBIT_AVS_MINT),
+            Some(&BYBIT_AVS_ADDRESS)
+        );
+    }
+
+    #[test]
+    fn test_quote_enforces_the_amount_bounds() {
+        let amm = bybit_amm_with(test_builder().min_amount(1_000).max_amount(1_000_000));
+        let err = amm.quote(&quote_params(999, SOLAYER_SOL, BYBIT_AVS_MINT)).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::AmountBelowMinimum {
+                amount: 999,
+                min_amount: 1_000,
+            })
+        );
+        let err = amm
+            .quote(&quote_params(1_000_001, BYBIT_AVS_MINT, SOLAYER_SOL))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::AmountAboveMaximum {
+                amount: 1_000_001,
+                max_amount: 1_000_000,
+            })
+        );
+        // both bounds are inclusive
+        for amount in [1_000, 1_000_000] {
+            assert!(amm.quote(&quote_params(amount, SOLAYER_SOL, BYBIT_AVS_MINT)).is_ok());
+        }
+        // no limits by default
+        assert!(bybit_amm().quote(&quote_params(1, SOLAYER_SOL, BYBIT_AVS_MINT)).is_ok());
+    }
+
+    #[test]
+    fn test_transfer_authority_distinct_from_the_owner() {
+        let amm = bybit_amm();
+        let owner = Pubkey::new_unique();
+        let swap_params = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+        let authority = swap_params.token_transfer_authority;
+        assert_ne!(authority, owner);
+
+        // the staker meta is the signing authority, not the owner of the token accounts
+        let SwapAndAccountMetas { account_metas, .. } = amm.get_swap_and_account_metas(&swap_params).unwrap();
+        assert_eq!(account_metas[0], AccountMeta::new_readonly(authority, true));
+        assert!(account_metas.iter().all(|meta| meta.pubkey != owner));
+
+        // owned by someone else, the authority needs an approval covering in_amount
+        let source = |delegated_amount| {
+            let mut data = vec![0; TokenAccount::LEN];
+            TokenAccount {
+                mint: SOLAYER_SOL,
+                owner,
+                amount: swap_params.in_amount,
+                delegate: COption::Some(authority),
+                delegated_amount,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            }
+            .pack_into_slice(&mut data);
+            Account {
+                data,
+                ..token_account(SOLAYER_SOL, owner, 0)
+            }
+        };
+        let approved = account_map([(swap_params.source_token_account, source(swap_params.in_amount))]);
+        assert_eq!(
+            SolayerEndoAVSAmm::validate_transfer_authority(&swap_params, &approved),
+            Ok(())
+        );
+
+        let short = account_map([(swap_params.source_token_account, source(swap_params.in_amount - 1))]);
+        assert_eq!(
+            SolayerEndoAVSAmm::validate_transfer_authority(&swap_params, &short),
+            Err(SolayerAmmError::UnauthorizedTransferAuthority {
+                authority,
+                token_account: swap_params.source_token_account,
+                owner,
+            })
+        );
+    }
+
+    #[test]
+    fn test_detailed_metas_report_the_direction() {
+        let amm = bybit_amm();
+        for (source_mint, destination_mint, direction) in [
+            (SOLAYER_SOL, BYBIT_AVS_MINT, SwapDirection::Delegate),
+            (BYBIT_AVS_MINT, SOLAYER_SOL, SwapDirection::Undelegate),
+        ] {
+            let swap_params = swap_params(source_mint, destination_mint);
+            let detailed = amm.get_swap_and_account_metas_detailed(&swap_params).unwrap();
+            assert_eq!(detailed.direction, direction);
+            assert_eq!(detailed.swap, direction.swap());
+            assert_eq!(detailed.endo_avs, BYBIT_AVS_ADDRESS);
+            let SwapAndAccountMetas { account_metas, .. } = amm.get_swap_and_account_metas(&swap_params).unwrap();
+            assert_eq!(detailed.account_metas, account_metas);
+        }
+        // pairs without exactly one sSOL side have no direction
+        for (source_mint, destination_mint) in [(SOLAYER_SOL, SOLAYER_SOL), (BYBIT_AVS_MINT, OKX_AVS_MINT)] {
+            assert!(amm
+                .get_swap_and_account_metas_detailed(&swap_params(source_mint, destination_mint))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_liquidity_state_follows_update() {
+        let mut amm = bybit_amm();
+        assert_eq!(amm.liquidity_state(), LiquidityState::Unknown);
+
+        load_reserves(&mut amm, [0, 7]);
+        assert_eq!(amm.liquidity_state(), LiquidityState::Available);
+
+        load_reserves(&mut amm, [0, 0]);
+        assert_eq!(amm.liquidity_state(), LiquidityState::Empty);
+    }
+
+    #[test]
+    fn test_quote_action_label_for_both_directions() {
+        let amm = bybit_amm_with(test_builder().avs_to_avs(true));
+        assert_eq!(
+            amm.quote_action_label(&quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT)),
+            "Delegate to Bybit AVS"
+        );
+        assert_eq!(
+            amm.quote_action_label(&quote_params(1_000, BYBIT_AVS_MINT, SOLAYER_SOL)),
+            "Undelegate from Bybit AVS"
+        );
+        assert_eq!(
+            amm.quote_action_label(&quote_params(1_000, OKX_AVS_MINT, BYBIT_AVS_MINT)),
+            "Swap OKX AVS to Bybit AVS"
+        );
+    }
+
+    #[test]
+    fn test_ssol_to_ssol_is_an_error_not_a_panic() {
+        let amm = bybit_amm();
+        let same_mint = Some(&SolayerAmmError::SameMint(SOLAYER_SOL));
+        let swap_params = swap_params(SOLAYER_SOL, SOLAYER_SOL);
+
+        let err = amm.quote(&quote_params(1_000, SOLAYER_SOL, SOLAYER_SOL)).unwrap_err();
+        assert_eq!(err.downcast_ref::<SolayerAmmError>(), same_mint);
+        let err = amm.get_swap_and_account_metas(&swap_params).err().unwrap();
+        assert_eq!(err.downcast_ref::<SolayerAmmError>(), same_mint);
+        let err = amm.build_instruction(&swap_params).unwrap_err();
+        assert_eq!(err.downcast_ref::<SolayerAmmError>(), same_mint);
+        assert_eq!(
+            SolayerSwapRequest::from_swap_params(&amm, &swap_params).err(),
+            Some(SolayerAmmError::SameMint(SOLAYER_SOL))
+        );
+        assert!(!amm.can_quote(&SOLAYER_SOL, &SOLAYER_SOL));
+    }
+
+    #[test]
+    fn test_undelegation_available_slot_with_a_cooldown() {
+        let amm = bybit_amm_with(test_builder().undelegation_cooldown_slots(100));
+        assert_eq!(amm.undelegation_available_slot(1_000), Some(1_100));
+        // saturates instead of wrapping
+        assert_eq!(amm.undelegation_available_slot(u64::MAX - 1), Some(u64::MAX));
+
+        assert_eq!(bybit_amm().undelegation_available_slot(1_000), None);
+    }
+
+    #[test]
+    fn test_validate_metas_catches_corrupted_metas() {
+        let SwapAndAccountMetas { account_metas, .. } = bybit_amm()
+            .get_swap_and_account_metas(&swap_params(SOLAYER_SOL, BYBIT_AVS_MINT))
+            .unwrap();
+        assert_eq!(SolayerEndoAVSAmm::validate_metas(&account_metas), Ok(()));
+
+        let corrupted = |corrupt: fn(&mut Vec<AccountMeta>)| {
+            let mut metas = account_metas.clone();
+            corrupt(&mut metas);
+            SolayerEndoAVSAmm::validate_metas(&metas)
+        };
+        assert_eq!(
+            corrupted(|metas| metas[1].is_signer = true),
+            Err(SolayerAmmError::InvalidAccountMetas {
+                index: 1,
+                reason: "only the staker signs",
+            })
+        );
+        assert_eq!(
+            corrupted(|metas| metas[0].is_signer = false),
+            Err(SolayerAmmError::InvalidAccountMetas {
+                index: 0,
+                reason: "only the staker signs",
+            })
+        );
+        assert_eq!(
+            corrupted(|metas| metas[3].is_writable = false),
+            Err(SolayerAmmError::InvalidAccountMetas {
+                index: 3,
+                reason: "unexpected writable flag",
+            })
+        );
+        assert_eq!(
+            corrupted(|metas| metas[SWAP_ACCOUNTS_LEN - 1].pubkey = Pubkey::new_unique()),
+            Err(SolayerAmmError::InvalidAccountMetas {
+                index: SWAP_ACCOUNTS_LEN - 1,
+                reason: "not a token program",
+            })
+        );
+        assert_eq!(
+            corrupted(|metas| {
+                metas.pop();
+            }),
+            Err(SolayerAmmError::InvalidAccountMetas {
+                index: SWAP_ACCOUNTS_LEN - 1,
+                reason: "wrong number of accounts",
+            })
+        );
+    }
+
+    #[test]
+    fn test_is_solayer_endo_avs_account_filters_pools() {
+        // sSOL on either side
+        assert!(is_solayer_endo_avs_account(&swap_account(
+            &swap_state(SOLAYER_SOL, BYBIT_AVS_MINT),
+            SOLAYER_DELEGATION_PROGRAM
+        )));
+        assert!(is_solayer_endo_avs_account(&swap_account(
+            &swap_state(BYBIT_AVS_MINT, SOLAYER_SOL),
+            SOLAYER_DELEGATION_PROGRAM
+        )));
+
+        // no sSOL side, another owner, or too short to hold a state
+        assert!(!is_solayer_endo_avs_account(&swap_account(
+            &swap_state(OKX_AVS_MINT, BYBIT_AVS_MINT),
+            SOLAYER_DELEGATION_PROGRAM
+        )));
+        assert!(!is_solayer_endo_avs_account(&swap_account(
+            &swap_state(SOLAYER_SOL, BYBIT_AVS_MINT),
+            Pubkey::new_unique()
+        )));
+        let mut truncated = swap_account(&swap_state(SOLAYER_SOL, BYBIT_AVS_MINT), SOLAYER_DELEGATION_PROGRAM);
+        truncated.data.truncate(SwapV1::LEN);
+        assert!(!is_solayer_endo_avs_account(&truncated));
+    }
+
+    #[test]
+    fn test_quote_and_swap_metas_read_one_state() {
+        let mut amm = bybit_amm();
+        load_reserves(&mut amm, [1_000_000, 1_000_000]);
+        let quote_params = quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT);
+        // stale mints and amounts, replaced by the quote's
+        let stale = swap_params(BYBIT_AVS_MINT, SOLAYER_SOL);
+
+        // separate calls: the pool empties between the quote and the metas, which still build
+        let quote = amm.quote(&quote_params).unwrap();
+        assert_eq!(quote.out_amount, 1_000);
+        load_reserves(&mut amm, [1_000_000, 0]);
+        assert!(amm
+            .get_swap_and_account_metas(&swap_params(SOLAYER_SOL, BYBIT_AVS_MINT))
+            .is_ok());
+
+        // one call sees the emptied pool for both
+        let err = amm.quote_and_swap_metas(&quote_params, &stale).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::NoLiquidity)
+        );
+
+        // and otherwise builds the metas for exactly the quoted swap
+        load_reserves(&mut amm, [1_000_000, 1_000_000]);
+        let (quote, swap_and_account_metas) = amm.quote_and_swap_metas(&quote_params, &stale).unwrap();
+        let quoted = SwapParams {
+            in_amount: quote.in_amount,
+            out_amount: quote.out_amount,
+            ..swap_params(SOLAYER_SOL, BYBIT_AVS_MINT)
+        };
+        let expected = amm
+            .get_swap_and_account_metas(&SwapParams {
+                source_token_account: stale.source_token_account,
+                destination_token_account: stale.destination_token_account,
+                token_transfer_authority: stale.token_transfer_authority,
+                ..quoted
+            })
+            .unwrap();
+        assert_eq!(swap_and_account_metas.swap, Swap::SolayerDelegateNoInit);
+        assert_eq!(swap_and_account_metas.account_metas, expected.account_metas);
+    }
+
+    #[cfg(feature = "mainnet-avs")]
+    #[test]
+    fn test_from_avs_name_ignores_case() {
+        assert_eq!(SolayerEndoAVSAmm::from_avs_name("Bybit"), Some(BYBIT_AVS_MINT));
+        assert_eq!(SolayerEndoAVSAmm::from_avs_name("bybit"), Some(BYBIT_AVS_MINT));
+        assert_eq!(
+            SolayerEndoAVSAmm::from_avs_name("KAMINO FINANCE"),
+            Some(KAMINO_FINANCE_AVS_MINT)
+        );
+        assert_eq!(SolayerEndoAVSAmm::from_avs_name("unknown"), None);
+        assert_eq!(SolayerEndoAVSAmm::from_avs_name(""), None);
+    }
+
+    #[test]
+    fn test_needs_destination_ata_creation() {
+        let amm = bybit_amm();
+        let swap_params = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+        let destination = swap_params.destination_token_account;
+        let owner = swap_params.token_transfer_authority;
+
+        assert!(amm.needs_destination_ata_creation(&swap_params, &account_map([])));
+        // a closed account holds no data and has to be created again
+        let closed = Account {
+            data: vec![],
+            ..token_account(BYBIT_AVS_MINT, owner, 0)
+        };
+        assert!(amm.needs_destination_ata_creation(&swap_params, &account_map([(destination, closed)])));
+        assert!(!amm.needs_destination_ata_creation(
+            &swap_params,
+            &account_map([(destination, token_account(BYBIT_AVS_MINT, owner, 0))])
+        ));
+    }
+
+    #[test]
+    fn test_pool_fee_account_matches_the_parsed_state() {
+        let pool_fee_account = Pubkey::new_unique();
+        let state = SwapV1 {
+            pool_fee_account: swap_pubkey(pool_fee_account),
+            ..swap_state(SOLAYER_SOL, BYBIT_AVS_MINT)
+        };
+        let data = swap_account(&state, SOLAYER_DELEGATION_PROGRAM).data;
+        let amm =
+            SolayerEndoAVSAmm::from_account_data(POOL_KEY, SOLAYER_DELEGATION_PROGRAM, &data, &amm_context()).unwrap();
+
+        assert_eq!(amm.pool_fee_account(), pool_fee_account);
+        assert_eq!(
+            amm.pool_fee_account(),
+            unpack_swap_state(&POOL_KEY, &data).unwrap().pool_fee_account()
+        );
+    }
+
+    #[test]
+    fn test_supported_avs_mints_order_is_stable() {
+        let mints = SolayerEndoAVSAmm::supported_avs_mints();
+        assert_eq!(mints, SolayerEndoAVSAmm::supported_avs_mints());
+        // the order of the source of truth, not of the hash maps built from it
+        let expected: Vec<Pubkey> = built_in_avs().iter().map(|avs| avs.mint).collect();
+        assert_eq!(mints, expected);
+        #[cfg(feature = "mainnet-avs")]
+        assert_eq!(mints.first(), Some(&BYBIT_AVS_MINT));
+    }
+
+    #[test]
+    fn test_quote_with_max_in_caps_exact_out() {
+        let amm = bybit_amm_with_trade_fee(25, 10_000);
+        let exact_out = QuoteParams {
+            swap_mode: SwapMode::ExactOut,
+            ..quote_params(1_000_000, SOLAYER_SOL, BYBIT_AVS_MINT)
+        };
+        let in_amount = amm.quote(&exact_out).unwrap().in_amount;
+        assert!(in_amount > 1_000_000);
+
+        let err = amm.quote_with_max_in(&exact_out, in_amount - 1).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::MaxInAmountExceeded {
+                in_amount,
+                max_in_amount: in_amount - 1,
+            })
+        );
+        let quote = amm.quote_with_max_in(&exact_out, in_amount).unwrap();
+        assert_eq!((quote.in_amount, quote.out_amount), (in_amount, 1_000_000));
+    }
+
+    #[test]
+    fn test_accounts_for_simulation_cover_the_metas() {
+        let amm =
+            bybit_amm_with(test_builder().token_programs(HashMap::from([(BYBIT_AVS_MINT, spl_token_2022::id())])));
+        let swap_params = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+        let accounts = amm.accounts_for_simulation(&swap_params).unwrap();
+
+        let SwapAndAccountMetas { account_metas, .. } = amm.get_swap_and_account_metas(&swap_params).unwrap();
+        assert!(account_metas.iter().all(|meta| accounts.contains(&meta.pubkey)));
+        for account in [
+            SOLAYER_SOL,
+            BYBIT_AVS_MINT,
+            SOLAYER_DELEGATION_PROGRAM,
+            spl_token::id(),
+            spl_token_2022::id(),
+        ] {
+            assert!(accounts.contains(&account), "{account}");
+        }
+        let unique: HashSet<&Pubkey> = accounts.iter().collect();
+        assert_eq!(unique.len(), accounts.len());
+    }
+
+    #[test]
+    fn test_unknown_mint_policy_error_and_skip() {
+        let unknown_mint = Pubkey::new_unique();
+        let params = quote_params(1_000, SOLAYER_SOL, unknown_mint);
+
+        // Error by default
+        let err = bybit_amm().quote(&params).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::UnknownAvsMint(unknown_mint))
+        );
+
+        // Skip returns an empty quote
+        let amm = bybit_amm_with(test_builder().unknown_mint_policy(UnknownMintPolicy::Skip));
+        let quote = amm.quote(&params).unwrap();
+        assert_eq!(quote_fields(&quote), quote_fields(&Quote::default()));
+        // a known pair still quotes, and other failures still error
+        assert_eq!(
+            amm.quote(&quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT))
+                .unwrap()
+                .out_amount,
+            1_000
+        );
+        assert!(amm.quote(&quote_params(0, SOLAYER_SOL, BYBIT_AVS_MINT)).is_err());
+    }
+
+    #[test]
+    fn test_routable_pairs_of_a_single_pool() {
+        let mut amm = bybit_amm();
+        assert_eq!(
+            amm.routable_pairs(),
+            [(SOLAYER_SOL, BYBIT_AVS_MINT), (BYBIT_AVS_MINT, SOLAYER_SOL)]
+        );
+        amm.set_direction_enabled(SwapDirection::Undelegate, false);
+        assert_eq!(amm.routable_pairs(), [(SOLAYER_SOL, BYBIT_AVS_MINT)]);
+    }
+
+    #[test]
+    fn test_referrer_adds_no_meta() {
+        let amm = bybit_amm();
+        let referrers = jupiter_amm_interface::QuoteMintToReferrer::from_iter([(SOLAYER_SOL, Pubkey::new_unique())]);
+        let with_referrer = SwapParams {
+            quote_mint_to_referrer: Some(&referrers),
+            ..swap_params(SOLAYER_SOL, BYBIT_AVS_MINT)
+        };
+        let without_referrer = SwapParams {
+            quote_mint_to_referrer: None,
+            ..with_referrer
+        };
+
+        // the delegation program has no referral slot, so the metas are unchanged
+        let SwapAndAccountMetas { account_metas, .. } = amm.get_swap_and_account_metas(&with_referrer).unwrap();
+        let SwapAndAccountMetas {
+            account_metas: expected,
+            ..
+        } = amm.get_swap_and_account_metas(&without_referrer).unwrap();
+        assert_eq!(account_metas, expected);
+        assert_eq!(account_metas.len(), amm.get_accounts_len());
+    }
+
+    #[test]
+    fn test_unpack_errors_name_the_account() {
+        let key = Pubkey::new_unique();
+        let mut data = swap_account(&swap_state(SOLAYER_SOL, BYBIT_AVS_MINT), SOLAYER_DELEGATION_PROGRAM).data;
+        // not a valid curve type
+        data[1 + SwapV1::LEN - 33] = 0xff;
+        assert!(matches!(
+            unpack_swap_state(&key, &data),
+            Err(SolayerAmmError::InvalidAccountData { .. })
+        ));
+
+        for data in [&data[..], &data[..100]] {
+            let err = SolayerEndoAVSAmm::from_account_data(key, SOLAYER_DELEGATION_PROGRAM, data, &amm_context())
+                .err()
+                .unwrap();
+            let message = format!("{err:#}");
+            assert!(message.contains(&key.to_string()), "{message}");
+            assert!(message.contains(&format!("{} bytes", data.len())), "{message}");
+            assert!(err.downcast_ref::<SolayerAmmError>().is_some());
+        }
+    }
+
+    #[test]
+    fn test_diff_reports_changed_reserves() {
+        let mut before = bybit_amm();
+        load_reserves(&mut before, [5_000, 7_000]);
+        let mut after = before.clone();
+        assert!(before.diff(&after).is_empty());
+
+        load_reserves(&mut after, [5_000, 6_000]);
+        assert_eq!(
+            before.diff(&after),
+            AmmDiff {
+                reserves: Some(([5_000, 7_000], [5_000, 6_000])),
+                ..AmmDiff::default()
+            }
+        );
+
+        // rate and fee changes are reported alongside
+        let rate = ExchangeRate {
+            numerator: 105,
+            denominator: 100,
+        };
+        let mut after = bybit_amm_with_trade_fee(25, 10_000);
+        load_reserves(&mut after, [5_000, 7_000]);
+        after.set_exchange_rate(Some(rate)).unwrap();
+        let diff = before.diff(&after);
+        assert_eq!(diff.reserves, None);
+        assert_eq!(diff.exchange_rate, Some((None, Some(rate))));
+        assert_eq!(diff.trade_fee, Some(((0, 0), (25, 10_000))));
+    }
+
+    #[test]
+    fn test_quote_for_pool_by_endo_avs_address() {
+        let amm = bybit_amm();
+        let params = quote_params(1_000, SOLAYER_SOL, BYBIT_AVS_MINT);
+        let quote = amm.quote_for_pool(&BYBIT_AVS_ADDRESS, &params).unwrap();
+        assert_eq!(quote_fields(&quote), quote_fields(&amm.quote(&params).unwrap()));
+
+        // a known endoAVS that isn't part of the quoted pair
+        let err = amm.quote_for_pool(&OKX_AVS_ADDRESS, &params).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::EndoAvsMintMismatch {
+                endo_avs: OKX_AVS_ADDRESS,
+                avs_mint: OKX_AVS_MINT,
+            })
+        );
+        let unknown = Pubkey::new_unique();
+        let err = amm.quote_for_pool(&unknown, &params).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::UnknownEndoAvs(unknown))
+        );
+    }
+
+    #[test]
+    fn test_update_keeps_the_last_reserve_of_a_missing_account() {
+        let mut amm = bybit_amm();
+        let [token_a, token_b] = amm.token_accounts();
+
+        // before both reserves were ever seen, they don't count as loaded
+        amm.update(&account_map([(token_a, token_account(SOLAYER_SOL, POOL_KEY, 5))]))
+            .unwrap();
+        assert_eq!(amm.reserves(), [5, 0]);
+        assert_eq!(amm.liquidity_state(), LiquidityState::Unknown);
+
+        load_reserves(&mut amm, [5_000, 7_000]);
+        // token_b went missing: token_a moves on, token_b keeps its last value
+        amm.update(&account_map([(token_a, token_account(SOLAYER_SOL, POOL_KEY, 6_000))]))
+            .unwrap();
+        assert_eq!(amm.reserves(), [6_000, 7_000]);
+        assert_eq!(amm.liquidity_state(), LiquidityState::Available);
+        // still asked for in the next update
+        assert!(amm.get_accounts_to_update().contains(&token_b));
+    }
+
+    #[test]
+    fn test_token_programs_option_drives_the_metas() {
+        let swap_params = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+        let SwapAndAccountMetas { account_metas, .. } = bybit_amm().get_swap_and_account_metas(&swap_params).unwrap();
+        // spl-token without configuration
+        assert_eq!(account_metas[SWAP_ACCOUNTS_LEN - 1].pubkey, spl_token::id());
+
+        let amm =
+            bybit_amm_with(test_builder().token_programs(HashMap::from([(BYBIT_AVS_MINT, spl_token_2022::id())])));
+        assert_eq!(amm.token_program(&BYBIT_AVS_MINT), spl_token_2022::id());
+        assert_eq!(amm.token_program(&SOLAYER_SOL), spl_token::id());
+        let SwapAndAccountMetas { account_metas, .. } = amm.get_swap_and_account_metas(&swap_params).unwrap();
+        assert_eq!(account_metas[SWAP_ACCOUNTS_LEN - 1].pubkey, spl_token_2022::id());
+    }
+
+    // sSOL -> Bybit into a watched destination, with the destination present or not at the last update
+    fn bybit_amm_watching_destination(swap_params: &SwapParams, exists: bool) -> SolayerEndoAVSAmm {
+        let mut amm = bybit_amm();
+        let destination = swap_params.destination_token_account;
+        amm.watch_destination_ata(destination);
+        let accounts = exists.then(|| {
+            (
+                destination,
+                token_account(BYBIT_AVS_MINT, swap_params.token_transfer_authority, 0),
+            )
+        });
+        amm.update(&account_map(accounts)).unwrap();
+        amm
+    }
+
+    #[test]
+    fn test_net_output_after_rent_subtracts_the_ata_rent() {
+        let rent = Rent::default();
+        let swap_params = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+        let ata_rent = rent.minimum_balance(TokenAccount::LEN);
+
+        let amm = bybit_amm_watching_destination(&swap_params, false);
+        assert_eq!(
+            amm.net_output_after_rent(&swap_params, &rent),
+            swap_params.out_amount - ata_rent
+        );
+
+        let amm = bybit_amm_watching_destination(&swap_params, true);
+        assert_eq!(amm.net_output_after_rent(&swap_params, &rent), swap_params.out_amount);
+    }
+
+    #[test]
+    fn test_uninitialized_pool_is_rejected() {
+        let state = SwapV1 {
+            is_initialized: false,
+            ..swap_state(SOLAYER_SOL, BYBIT_AVS_MINT)
+        };
+        let keyed_account = KeyedAccount {
+            key: POOL_KEY,
+            account: swap_account(&state, SOLAYER_DELEGATION_PROGRAM),
+            params: None,
+        };
+        let err = SolayerEndoAVSAmm::from_keyed_account(&keyed_account, &amm_context())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::UninitializedPool(POOL_KEY))
+        );
+    }
+
+    #[test]
+    fn test_required_prefund_lamports_only_when_the_ata_is_created() {
+        let rent = Rent::default();
+        let swap_params = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+
+        let amm = bybit_amm_watching_destination(&swap_params, false);
+        assert_eq!(
+            amm.required_prefund_lamports(&swap_params, &rent),
+            rent.minimum_balance(TokenAccount::LEN)
+        );
+        let amm = bybit_amm_watching_destination(&swap_params, true);
+        assert_eq!(amm.required_prefund_lamports(&swap_params, &rent), 0);
+        // nothing is watched, so nothing is created
+        assert_eq!(bybit_amm().required_prefund_lamports(&swap_params, &rent), 0);
+    }
+
+    #[test]
+    fn test_swap_request_from_swap_params_resolves_each_role() {
+        let amm = bybit_amm();
+
+        let delegate = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+        assert_eq!(
+            SolayerSwapRequest::from_swap_params(&amm, &delegate).unwrap(),
+            SolayerSwapRequest {
+                direction: SwapDirection::Delegate,
+                endo_avs: BYBIT_AVS_ADDRESS,
+                staker: delegate.token_transfer_authority,
+                delegated_token_mint: SOLAYER_SOL,
+                avs_token_mint: BYBIT_AVS_MINT,
+                staker_delegated_token_account: delegate.source_token_account,
+                staker_avs_token_account: delegate.destination_token_account,
+                in_amount: delegate.in_amount,
+            }
+        );
+
+        let undelegate = swap_params(BYBIT_AVS_MINT, SOLAYER_SOL);
+        assert_eq!(
+            SolayerSwapRequest::from_swap_params(&amm, &undelegate).unwrap(),
+            SolayerSwapRequest {
+                direction: SwapDirection::Undelegate,
+                endo_avs: BYBIT_AVS_ADDRESS,
+                staker: undelegate.token_transfer_authority,
+                delegated_token_mint: SOLAYER_SOL,
+                avs_token_mint: BYBIT_AVS_MINT,
+                staker_delegated_token_account: undelegate.destination_token_account,
+                staker_avs_token_account: undelegate.source_token_account,
+                in_amount: undelegate.in_amount,
+            }
+        );
+    }
+
+    #[test]
+    fn test_swap_request_from_swap_params_rejects_invalid_params() {
+        let amm = bybit_amm();
+
+        let mut zero_amount = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+        zero_amount.in_amount = 0;
+        assert_eq!(
+            SolayerSwapRequest::from_swap_params(&amm, &zero_amount).err(),
+            Some(SolayerAmmError::ZeroAmount)
+        );
+
+        let mut no_source = swap_params(SOLAYER_SOL, BYBIT_AVS_MINT);
+        no_source.source_token_account = Pubkey::default();
+        assert_eq!(
+            SolayerSwapRequest::from_swap_params(&amm, &no_source).err(),
+            Some(SolayerAmmError::MissingTokenAccount {
+                side: "source",
+                mint: SOLAYER_SOL
+            })
+        );
+
+        let mut no_destination = swap_params(BYBIT_AVS_MINT, SOLAYER_SOL);
+        no_destination.destination_token_account = Pubkey::default();
+        assert_eq!(
+            SolayerSwapRequest::from_swap_params(&amm, &no_destination).err(),
+            Some(SolayerAmmError::MissingTokenAccount {
+                side: "destination",
+                mint: SOLAYER_SOL
+            })
+        );
+
+        let unknown_mint = Pubkey::new_unique();
+        assert_eq!(
+            SolayerSwapRequest::from_swap_params(&amm, &swap_params(SOLAYER_SOL, unknown_mint)).err(),
+            Some(SolayerAmmError::UnknownAvsMint(unknown_mint))
+        );
+    }
+
+    #[test]
+    fn test_pool_without_ssol_follows_the_missing_ssol_policy() {
+        let keyed_account = KeyedAccount {
+            key: POOL_KEY,
+            account: swap_account(&swap_state(OKX_AVS_MINT, BYBIT_AVS_MINT), SOLAYER_DELEGATION_PROGRAM),
+            params: None,
+        };
+
+        // warns by default and builds anyway
+        let amm = test_builder().build(&keyed_account, &amm_context()).unwrap();
+        assert_eq!(amm.key(), POOL_KEY);
+
+        let err = test_builder()
+            .missing_ssol_policy(MissingSsolPolicy::Error)
+            .build(&keyed_account, &amm_context())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<SolayerAmmError>(),
+            Some(&SolayerAmmError::MissingSsolReserve {
+                account: POOL_KEY,
+                ssol_mint: SOLAYER_SOL,
+            })
+        );
+    }
+
+    #[test]
+    fn test_another_pools_avs_mint_is_rejected() {
+        // OKX is a known AVS, but this pool holds Bybit AVS
+        let amm = bybit_amm();
+        for (input_mint, output_mint) in [(SOLAYER_SOL, OKX_AVS_MINT), (OKX_AVS_MINT, SOLAYER_SOL)] {
+            let unsupported = SolayerAmmError::UnsupportedMintPair {
+                input_mint,
+                output_mint,
+            };
+            let err = amm.quote(&quote_params(1_000, input_mint, output_mint)).unwrap_err();
+            assert_eq!(err.downcast_ref::<SolayerAmmError>(), Some(&unsupported));
+            let swap_params = swap_params(input_mint, output_mint);
+            let err = amm.get_swap_and_account_metas(&swap_params).err().unwrap();
+            assert_eq!(err.downcast_ref::<SolayerAmmError>(), Some(&unsupported));
+            assert_eq!(
+                SolayerSwapRequest::from_swap_params(&amm, &swap_params).err(),
+                Some(unsupported)
+            );
+            assert!(!amm.can_quote(&input_mint, &output_mint));
+        }
+
+        // still reachable as one leg of AVS -> AVS
+        let amm = bybit_amm_with(test_builder().avs_to_avs(true));
+        assert!(amm
+            .get_avs_to_avs_swap_legs(&swap_params(OKX_AVS_MINT, BYBIT_AVS_MINT))
+            .is_ok());
     }
 }
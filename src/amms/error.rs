@@ -0,0 +1,115 @@
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+use crate::amms::amm::SwapDirection;
+
+/// Failure kinds of the endoAVS AMM, so callers can match on them instead of on strings
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum SolayerAmmError {
+    #[error("swap account data is empty")]
+    EmptyAccountData,
+    #[error("unsupported swap account version {version}, expected {expected}")]
+    UnsupportedVersion { version: u8, expected: u8 },
+    #[error("swap account data too short: expected {expected} bytes, got {actual}")]
+    AccountDataTooShort { expected: usize, actual: usize },
+    #[error("account {account} is owned by {owner}, expected {expected}")]
+    UnexpectedOwner {
+        account: Pubkey,
+        owner: Pubkey,
+        expected: Pubkey,
+    },
+    #[error("swap account {0} is not initialized")]
+    UninitializedPool(Pubkey),
+    #[error("swap account {account} has {ssol_mint} on neither side")]
+    MissingSsolReserve { account: Pubkey, ssol_mint: Pubkey },
+    #[error("invalid {account} account data: {reason}")]
+    InvalidAccountData { account: Pubkey, reason: String },
+    #[error("unknown AVS mint {0}")]
+    UnknownAvsMint(Pubkey),
+    #[error("endoAVS address {address} for AVS mint {avs_mint} doesn't match the derived {derived}")]
+    EndoAvsAddressMismatch {
+        avs_mint: Pubkey,
+        address: Pubkey,
+        derived: Pubkey,
+    },
+    #[error("unknown endoAVS {0}")]
+    UnknownEndoAvs(Pubkey),
+    #[error("endoAVS {endo_avs} serves AVS mint {avs_mint}, which the quote doesn't involve")]
+    EndoAvsMintMismatch { endo_avs: Pubkey, avs_mint: Pubkey },
+    #[error("endoAVS {0} has no data")]
+    EndoAvsClosed(Pubkey),
+    #[error("input and output mint are both {0}")]
+    SameMint(Pubkey),
+    #[error("unsupported mint pair {input_mint} -> {output_mint}, expected sSOL and an AVS mint")]
+    UnsupportedMintPair { input_mint: Pubkey, output_mint: Pubkey },
+    #[error("cannot swap or quote a zero amount")]
+    ZeroAmount,
+    #[error("amount {amount} is below the minimum of {min_amount}")]
+    AmountBelowMinimum { amount: u64, min_amount: u64 },
+    #[error("amount {amount} is above the maximum of {max_amount}")]
+    AmountAboveMaximum { amount: u64, max_amount: u64 },
+    #[error("required input {in_amount} exceeds the maximum of {max_in_amount}")]
+    MaxInAmountExceeded { in_amount: u64, max_in_amount: u64 },
+    #[error("AVS pool has no liquidity")]
+    NoLiquidity,
+    #[error("pool opens at epoch {activation_epoch}, current epoch is {epoch}")]
+    NotYetActive { epoch: u64, activation_epoch: u64 },
+    #[error("{0:?} is currently disabled for this pool")]
+    DirectionDisabled(SwapDirection),
+    #[error("{side} token account for {mint} is missing")]
+    MissingTokenAccount { side: &'static str, mint: Pubkey },
+    #[error("{side} token account {address} holds mint {mint}, expected {expected_mint}")]
+    TokenAccountMintMismatch {
+        side: &'static str,
+        address: Pubkey,
+        mint: Pubkey,
+        expected_mint: Pubkey,
+    },
+    #[error("{authority} can't transfer from token account {token_account} owned by {owner}")]
+    UnauthorizedTransferAuthority {
+        authority: Pubkey,
+        token_account: Pubkey,
+        owner: Pubkey,
+    },
+    #[error("invalid swap account meta {index}: {reason}")]
+    InvalidAccountMetas { index: usize, reason: &'static str },
+    #[error("invalid trade fee {numerator}/{denominator}")]
+    InvalidTradeFee { numerator: u64, denominator: u64 },
+    #[error("invalid exchange rate {numerator}/{denominator}")]
+    InvalidExchangeRate { numerator: u64, denominator: u64 },
+    #[error("AVS fee of {fee_bps} bps for {avs_mint} is above 10000 bps")]
+    InvalidAvsFeeBps { avs_mint: Pubkey, fee_bps: u16 },
+    #[error("the AVS fee of {avs_mint} takes the whole amount, no input gives an exact output")]
+    AvsFeeTakesAll { avs_mint: Pubkey },
+    #[error("{0} calculation overflowed")]
+    MathOverflow(&'static str),
+    #[error("no pool can quote {input_mint} -> {output_mint}")]
+    NoRoutablePool { input_mint: Pubkey, output_mint: Pubkey },
+}
+
+/// Failure kinds of the SOL <-> sSOL restaking AMM
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum SolayerRestakingError {
+    #[error("invalid stake pool data: {0}")]
+    InvalidStakePool(String),
+    #[error("account is not an initialized stake pool")]
+    NotAStakePool,
+    #[error("account {account} is owned by {owner}, expected {expected}")]
+    UnexpectedOwner {
+        account: Pubkey,
+        owner: Pubkey,
+        expected: Pubkey,
+    },
+    #[error("stake pool mint {0} is not sSOL")]
+    UnexpectedPoolMint(Pubkey),
+    #[error("stake pool {0} is missing from the account map")]
+    MissingStakePool(Pubkey),
+    #[error("unsupported mint pair {input_mint} -> {output_mint}, expected SOL and sSOL")]
+    UnsupportedMintPair { input_mint: Pubkey, output_mint: Pubkey },
+    #[error("{0} calculation overflowed")]
+    MathOverflow(&'static str),
+    #[error("ExactOut is not supported")]
+    ExactOutNotSupported,
+    #[error("jupiter-amm-interface has no Swap variant for spl-stake-pool {0}, use build_instruction")]
+    NoSwapVariant(&'static str),
+}
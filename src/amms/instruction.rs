@@ -0,0 +1,36 @@
+// Anchor discriminators, sha256("global:<instruction name>")[..8]
+pub const DELEGATE_DISCRIMINATOR: [u8; 8] = [90, 147, 75, 178, 85, 88, 4, 137];
+pub const UNDELEGATE_DISCRIMINATOR: [u8; 8] = [131, 148, 180, 198, 91, 104, 42, 238];
+
+/// Data of `delegate`: the discriminator followed by the sSOL amount as a little endian u64
+pub fn delegate_data(amount: u64) -> Vec<u8> {
+    [DELEGATE_DISCRIMINATOR, amount.to_le_bytes()].concat()
+}
+
+/// Data of `undelegate`: the discriminator followed by the AVS token amount as a little endian u64
+pub fn undelegate_data(amount: u64) -> Vec<u8> {
+    [UNDELEGATE_DISCRIMINATOR, amount.to_le_bytes()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delegate_data_layout() {
+        assert_eq!(
+            delegate_data(1_000_000_000),
+            [90, 147, 75, 178, 85, 88, 4, 137, 0, 202, 154, 59, 0, 0, 0, 0]
+        );
+        assert_eq!(delegate_data(u64::MAX)[8..], [0xff; 8]);
+    }
+
+    #[test]
+    fn test_undelegate_data_layout() {
+        assert_eq!(
+            undelegate_data(1_000_000_000),
+            [131, 148, 180, 198, 91, 104, 42, 238, 0, 202, 154, 59, 0, 0, 0, 0]
+        );
+        assert_eq!(undelegate_data(0)[8..], [0; 8]);
+    }
+}
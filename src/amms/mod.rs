@@ -1 +1,5 @@
 pub mod amm;
+pub mod error;
+pub mod instruction;
+pub mod registry;
+pub mod restaking;
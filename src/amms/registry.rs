@@ -0,0 +1,163 @@
+#[cfg(feature = "registry")]
+use std::collections::HashMap;
+#[cfg(feature = "registry")]
+use std::path::Path;
+#[cfg(feature = "registry")]
+use std::str::FromStr;
+
+#[cfg(feature = "registry")]
+use anyhow::Context;
+use anyhow::Result;
+use jupiter_amm_interface::{AccountMap, Amm, AmmContext, KeyedAccount, Quote, QuoteParams, SwapMode};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::amms::amm::{SolayerEndoAVSAmm, SolayerEndoAVSAmmBuilder};
+use crate::amms::error::SolayerAmmError;
+
+// One entry of an AVS registry JSON file
+#[cfg(feature = "registry")]
+#[derive(serde::Deserialize)]
+struct AvsEntry {
+    name: String,
+    mint: String,
+    address: String,
+}
+
+/// Holds one `SolayerEndoAVSAmm` per endoAVS pool and routes quotes to the matching one
+#[derive(Clone, Default)]
+pub struct SolayerAmmRegistry {
+    amms: Vec<SolayerEndoAVSAmm>,
+}
+
+impl SolayerAmmRegistry {
+    pub fn new(keyed_accounts: &[KeyedAccount], amm_context: &AmmContext) -> Result<Self> {
+        Self::with_builder(keyed_accounts, amm_context, SolayerEndoAVSAmmBuilder::new())
+    }
+
+    /// Builds every pool with the same builder settings
+    pub fn with_builder(
+        keyed_accounts: &[KeyedAccount],
+        amm_context: &AmmContext,
+        builder: SolayerEndoAVSAmmBuilder,
+    ) -> Result<Self> {
+        let amms = keyed_accounts
+            .iter()
+            .map(|keyed_account| builder.clone().build(keyed_account, amm_context))
+            .collect::<Result<_>>()?;
+        Ok(Self { amms })
+    }
+
+    /// Builds every pool with the AVS mint -> endoAVS address overrides read from the JSON file
+    /// at `path`, see `avs_overrides_from_json_path`
+    #[cfg(feature = "registry")]
+    pub fn from_json_path(
+        path: impl AsRef<Path>,
+        keyed_accounts: &[KeyedAccount],
+        amm_context: &AmmContext,
+    ) -> Result<Self> {
+        let builder = SolayerEndoAVSAmmBuilder::new().avs_overrides(Self::avs_overrides_from_json_path(path)?);
+        Self::with_builder(keyed_accounts, amm_context, builder)
+    }
+
+    /// Reads AVS mint -> endoAVS address overrides for `SolayerEndoAVSAmmBuilder::avs_overrides`
+    /// from a JSON array of `{"name": ..., "mint": ..., "address": ...}` entries
+    #[cfg(feature = "registry")]
+    pub fn avs_overrides_from_json_path(path: impl AsRef<Path>) -> Result<HashMap<Pubkey, Pubkey>> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        // serde_json errors carry the line and column
+        let entries: Vec<AvsEntry> =
+            serde_json::from_str(&json).with_context(|| format!("invalid AVS registry {}", path.display()))?;
+
+        entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let parse = |field: &str, value: &str| {
+                    Pubkey::from_str(value)
+                        .with_context(|| format!("entry {index} ({}): invalid {field} {value}", entry.name))
+                };
+                Ok((parse("mint", &entry.mint)?, parse("address", &entry.address)?))
+            })
+            .collect()
+    }
+
+    pub fn amms(&self) -> &[SolayerEndoAVSAmm] {
+        &self.amms
+    }
+
+    /// The first pool trading `input_mint` against `output_mint`
+    pub fn get(&self, input_mint: &Pubkey, output_mint: &Pubkey) -> Option<&SolayerEndoAVSAmm> {
+        self.pools_for(*input_mint, *output_mint).next()
+    }
+
+    fn pools_for(&self, input_mint: Pubkey, output_mint: Pubkey) -> impl Iterator<Item = &SolayerEndoAVSAmm> {
+        self.amms.iter().filter(move |amm| {
+            let reserve_mints = amm.get_reserve_mints();
+            reserve_mints.contains(&input_mint) && reserve_mints.contains(&output_mint)
+        })
+    }
+
+    /// Directed pairs routable through any of the pools, without duplicates
+    pub fn routable_pairs(&self) -> Vec<(Pubkey, Pubkey)> {
+        let mut pairs: Vec<(Pubkey, Pubkey)> = self.amms.iter().flat_map(|amm| amm.routable_pairs()).collect();
+        pairs.sort();
+        pairs.dedup();
+        pairs
+    }
+
+    /// Union of the accounts every pool needs in `update`
+    pub fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        let mut accounts: Vec<Pubkey> = self.amms.iter().flat_map(|amm| amm.get_accounts_to_update()).collect();
+        accounts.sort();
+        accounts.dedup();
+        accounts
+    }
+
+    pub fn update(&mut self, account_map: &AccountMap) -> Result<()> {
+        self.amms.iter_mut().try_for_each(|amm| amm.update(account_map))
+    }
+
+    /// Quotes ExactIn `amount` against the pool holding both mints, returning that pool with the quote
+    ///
+    /// When every such pool fails to quote, e.g. on `AmountBelowMinimum`, the last pool's error is
+    /// returned as is, `NoRoutablePool` only when no pool holds the pair.
+    pub fn quote_best(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+    ) -> Result<(&SolayerEndoAVSAmm, Quote)> {
+        let quote_params = QuoteParams {
+            amount,
+            input_mint,
+            output_mint,
+            swap_mode: SwapMode::ExactIn,
+        };
+        let mut best: Option<(&SolayerEndoAVSAmm, Quote)> = None;
+        let mut last_err = None;
+        for amm in self.pools_for(input_mint, output_mint) {
+            match amm.quote(&quote_params) {
+                // the last of equal quotes wins
+                Ok(quote)
+                    if best
+                        .as_ref()
+                        .is_none_or(|(_, best)| quote.out_amount >= best.out_amount) =>
+                {
+                    best = Some((amm, quote))
+                }
+                Ok(_) => {}
+                Err(err) => last_err = Some(err),
+            }
+        }
+        best.ok_or_else(|| {
+            last_err.unwrap_or_else(|| {
+                SolayerAmmError::NoRoutablePool {
+                    input_mint,
+                    output_mint,
+                }
+                .into()
+            })
+        })
+    }
+}
@@ -0,0 +1,445 @@
+use anyhow::Result;
+use jupiter_amm_interface::{
+    AccountMap, Amm, AmmContext, KeyedAccount, Quote, QuoteParams, SwapAndAccountMetas, SwapMode, SwapParams,
+};
+use solana_sdk::borsh1::try_from_slice_unchecked;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use spl_stake_pool::find_withdraw_authority_program_address;
+use spl_stake_pool::state::{AccountType, StakePool};
+use spl_token::native_mint;
+
+use crate::amms::amm::SOLAYER_SOL;
+use crate::amms::error::SolayerRestakingError;
+
+/// SOL -> sSOL deposits or sSOL -> SOL withdrawals against the stake pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestakingDirection {
+    DepositSol,
+    WithdrawSol,
+}
+
+impl RestakingDirection {
+    fn from_mints(input_mint: &Pubkey, output_mint: &Pubkey) -> Result<Self, SolayerRestakingError> {
+        match (*input_mint, *output_mint) {
+            (input, output) if input == native_mint::id() && output == SOLAYER_SOL => Ok(Self::DepositSol),
+            (input, output) if input == SOLAYER_SOL && output == native_mint::id() => Ok(Self::WithdrawSol),
+            _ => Err(SolayerRestakingError::UnsupportedMintPair {
+                input_mint: *input_mint,
+                output_mint: *output_mint,
+            }),
+        }
+    }
+}
+
+/// Quotes SOL <-> sSOL against the Solayer stake pool
+///
+/// `jupiter_amm_interface::Swap` has no variant for spl-stake-pool `DepositSol`/`WithdrawSol`,
+/// the StakeDex ones expect StakeDex accounts, so `get_swap_and_account_metas` refuses to build
+/// metas and the instruction comes from `build_instruction` instead. The pool reports itself
+/// inactive so routers don't pick a route they then fail to build.
+#[derive(Clone)]
+pub struct SolayerRestakingAmm {
+    key: Pubkey,
+    label: String,
+    state: StakePool,
+    withdraw_authority: Pubkey,
+    reserve_mints: [Pubkey; 2],
+    program_id: Pubkey,
+}
+
+impl SolayerRestakingAmm {
+    fn unpack_stake_pool(data: &[u8]) -> Result<StakePool, SolayerRestakingError> {
+        let state = try_from_slice_unchecked::<StakePool>(data)
+            .map_err(|err| SolayerRestakingError::InvalidStakePool(err.to_string()))?;
+        if state.account_type != AccountType::StakePool {
+            return Err(SolayerRestakingError::NotAStakePool);
+        }
+        if state.pool_mint != SOLAYER_SOL {
+            return Err(SolayerRestakingError::UnexpectedPoolMint(state.pool_mint));
+        }
+        Ok(state)
+    }
+
+    /// The spl-stake-pool `DepositSol` or `WithdrawSol` instruction for this swap, by its mints
+    pub fn build_instruction(&self, swap_params: &SwapParams) -> Result<Instruction, SolayerRestakingError> {
+        let direction = RestakingDirection::from_mints(&swap_params.source_mint, &swap_params.destination_mint)?;
+        Ok(match direction {
+            RestakingDirection::DepositSol => spl_stake_pool::instruction::deposit_sol(
+                &self.program_id,
+                &self.key,
+                &self.withdraw_authority,
+                &self.state.reserve_stake,
+                &swap_params.token_transfer_authority,
+                &swap_params.destination_token_account,
+                &self.state.manager_fee_account,
+                // no referrer, the referral fee goes back to the manager
+                &self.state.manager_fee_account,
+                &self.state.pool_mint,
+                &self.state.token_program_id,
+                swap_params.in_amount,
+            ),
+            // burns sSOL, the lamports go to the owner's wallet, not to a token account
+            RestakingDirection::WithdrawSol => spl_stake_pool::instruction::withdraw_sol(
+                &self.program_id,
+                &self.key,
+                &self.withdraw_authority,
+                &swap_params.token_transfer_authority,
+                &swap_params.source_token_account,
+                &self.state.reserve_stake,
+                &swap_params.token_transfer_authority,
+                &self.state.manager_fee_account,
+                &self.state.pool_mint,
+                &self.state.token_program_id,
+                swap_params.in_amount,
+            ),
+        })
+    }
+}
+
+impl Amm for SolayerRestakingAmm {
+    fn from_keyed_account(keyed_account: &KeyedAccount, _amm_context: &AmmContext) -> Result<Self> {
+        let program_id = keyed_account.account.owner;
+        if program_id != spl_stake_pool::id() {
+            return Err(SolayerRestakingError::UnexpectedOwner {
+                account: keyed_account.key,
+                owner: program_id,
+                expected: spl_stake_pool::id(),
+            }
+            .into());
+        }
+        let state = Self::unpack_stake_pool(&keyed_account.account.data)?;
+        let (withdraw_authority, _) = find_withdraw_authority_program_address(&program_id, &keyed_account.key);
+
+        Ok(Self {
+            key: keyed_account.key,
+            label: "Solayer".into(),
+            state,
+            withdraw_authority,
+            // SOL is deposited, sSOL is the pool token
+            reserve_mints: [native_mint::id(), SOLAYER_SOL],
+            program_id,
+        })
+    }
+
+    fn label(&self) -> String {
+        self.label.clone()
+    }
+
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        self.reserve_mints.to_vec()
+    }
+
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        // total_lamports and pool_token_supply live on the stake pool itself
+        vec![self.key]
+    }
+
+    fn update(&mut self, account_map: &AccountMap) -> Result<()> {
+        let account = account_map
+            .get(&self.key)
+            .ok_or(SolayerRestakingError::MissingStakePool(self.key))?;
+        self.state = Self::unpack_stake_pool(&account.data)?;
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        if quote_params.swap_mode == SwapMode::ExactOut {
+            return Err(SolayerRestakingError::ExactOutNotSupported.into());
+        }
+
+        let direction = RestakingDirection::from_mints(&quote_params.input_mint, &quote_params.output_mint)?;
+        let (out_amount, fee_amount, fee_mint) = match direction {
+            RestakingDirection::DepositSol => {
+                // SOL -> sSOL, the deposit fee is taken from the minted pool tokens
+                let pool_tokens = self
+                    .state
+                    .calc_pool_tokens_for_deposit(quote_params.amount)
+                    .ok_or(SolayerRestakingError::MathOverflow("deposit"))?;
+                let fee = self
+                    .state
+                    .calc_pool_tokens_sol_deposit_fee(pool_tokens)
+                    .ok_or(SolayerRestakingError::MathOverflow("deposit fee"))?;
+                (pool_tokens.saturating_sub(fee), fee, SOLAYER_SOL)
+            }
+            RestakingDirection::WithdrawSol => {
+                // sSOL -> SOL, the withdrawal fee is taken from the burnt pool tokens
+                let fee = self
+                    .state
+                    .calc_pool_tokens_sol_withdrawal_fee(quote_params.amount)
+                    .ok_or(SolayerRestakingError::MathOverflow("withdrawal fee"))?;
+                let lamports = self
+                    .state
+                    .calc_lamports_withdraw_amount(quote_params.amount.saturating_sub(fee))
+                    .ok_or(SolayerRestakingError::MathOverflow("withdrawal"))?;
+                (lamports, fee, SOLAYER_SOL)
+            }
+        };
+
+        Ok(Quote {
+            in_amount: quote_params.amount,
+            out_amount,
+            fee_amount,
+            fee_mint,
+            ..Quote::default()
+        })
+    }
+
+    /// Always fails: no `Swap` variant describes a bare spl-stake-pool deposit or withdrawal,
+    /// reporting a StakeDex one would make routers build a StakeDex CPI around these accounts
+    fn get_swap_and_account_metas(&self, swap_params: &SwapParams) -> Result<SwapAndAccountMetas> {
+        let instruction = match RestakingDirection::from_mints(&swap_params.source_mint, &swap_params.destination_mint)? {
+            RestakingDirection::DepositSol => "DepositSol",
+            RestakingDirection::WithdrawSol => "WithdrawSol",
+        };
+        Err(SolayerRestakingError::NoSwapVariant(instruction).into())
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    // Not routable until a `Swap` variant lets `get_swap_and_account_metas` succeed
+    fn is_active(&self) -> bool {
+        false
+    }
+
+    fn get_accounts_len(&self) -> usize {
+        // WithdrawSol takes two more sysvars and the stake program than DepositSol
+        12
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jupiter_amm_interface::ClockRef;
+    use solana_sdk::account::Account;
+
+    use super::*;
+
+    // 1_050 SOL backing 1_000 sSOL, with no fees
+    fn stake_pool_account() -> KeyedAccount {
+        let state = StakePool {
+            account_type: AccountType::StakePool,
+            pool_mint: SOLAYER_SOL,
+            reserve_stake: Pubkey::new_unique(),
+            manager_fee_account: Pubkey::new_unique(),
+            token_program_id: spl_token::id(),
+            total_lamports: 1_050_000_000_000,
+            pool_token_supply: 1_000_000_000_000,
+            ..StakePool::default()
+        };
+        KeyedAccount {
+            key: Pubkey::new_unique(),
+            account: Account {
+                lamports: 0,
+                data: borsh::to_vec(&state).unwrap(),
+                owner: spl_stake_pool::id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+            params: None,
+        }
+    }
+
+    fn amm_context() -> AmmContext {
+        AmmContext {
+            clock_ref: ClockRef::default(),
+        }
+    }
+
+    fn quote_params(input_mint: Pubkey, output_mint: Pubkey) -> QuoteParams {
+        QuoteParams {
+            amount: 1_050_000_000,
+            input_mint,
+            output_mint,
+            swap_mode: SwapMode::ExactIn,
+        }
+    }
+
+    fn swap_params<'a>(
+        source_mint: Pubkey,
+        destination_mint: Pubkey,
+        jupiter_program_id: &'a Pubkey,
+    ) -> SwapParams<'a, 'a> {
+        SwapParams {
+            swap_mode: SwapMode::ExactIn,
+            in_amount: 1_000_000_000,
+            out_amount: 1_000_000_000,
+            source_mint,
+            destination_mint,
+            source_token_account: Pubkey::new_unique(),
+            destination_token_account: Pubkey::new_unique(),
+            token_transfer_authority: Pubkey::new_unique(),
+            open_order_address: None,
+            quote_mint_to_referrer: None,
+            jupiter_program_id,
+            missing_dynamic_accounts_as_default: false,
+        }
+    }
+
+    #[test]
+    fn test_from_keyed_account() {
+        let keyed_account = stake_pool_account();
+        let amm = SolayerRestakingAmm::from_keyed_account(&keyed_account, &amm_context()).unwrap();
+
+        assert_eq!(amm.key(), keyed_account.key);
+        assert_eq!(amm.program_id(), spl_stake_pool::id());
+        assert_eq!(amm.get_reserve_mints(), vec![native_mint::id(), SOLAYER_SOL]);
+        assert_eq!(amm.get_accounts_to_update(), vec![keyed_account.key]);
+    }
+
+    #[test]
+    fn test_from_keyed_account_wrong_owner() {
+        let mut keyed_account = stake_pool_account();
+        keyed_account.account.owner = Pubkey::new_unique();
+
+        let err = SolayerRestakingAmm::from_keyed_account(&keyed_account, &amm_context())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<SolayerRestakingError>(),
+            Some(SolayerRestakingError::UnexpectedOwner { .. })
+        ));
+    }
+
+    #[test]
+    fn test_quote_at_pool_rate() {
+        let amm = SolayerRestakingAmm::from_keyed_account(&stake_pool_account(), &amm_context()).unwrap();
+
+        let deposit = amm.quote(&quote_params(native_mint::id(), SOLAYER_SOL)).unwrap();
+        assert_eq!(deposit.out_amount, 1_000_000_000);
+        let withdraw = amm.quote(&quote_params(SOLAYER_SOL, native_mint::id())).unwrap();
+        assert_eq!(withdraw.out_amount, 1_102_500_000);
+    }
+
+    #[test]
+    fn test_update_refreshes_rate() {
+        let keyed_account = stake_pool_account();
+        let mut amm = SolayerRestakingAmm::from_keyed_account(&keyed_account, &amm_context()).unwrap();
+
+        let mut state = amm.state.clone();
+        state.total_lamports = state.pool_token_supply;
+        let mut account = keyed_account.account.clone();
+        account.data = borsh::to_vec(&state).unwrap();
+        let account_map = AccountMap::from_iter([(keyed_account.key, account)]);
+        amm.update(&account_map).unwrap();
+
+        let deposit = amm.quote(&quote_params(native_mint::id(), SOLAYER_SOL)).unwrap();
+        assert_eq!(deposit.out_amount, 1_050_000_000);
+    }
+
+    #[test]
+    fn test_unsupported_mint_pair() {
+        let amm = SolayerRestakingAmm::from_keyed_account(&stake_pool_account(), &amm_context()).unwrap();
+        let other_mint = Pubkey::new_unique();
+        let jupiter_program_id = Pubkey::new_unique();
+
+        let err = amm.quote(&quote_params(other_mint, native_mint::id())).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SolayerRestakingError>(),
+            Some(SolayerRestakingError::UnsupportedMintPair { .. })
+        ));
+        // not taken for a withdrawal just because the source isn't SOL
+        assert!(amm
+            .build_instruction(&swap_params(other_mint, native_mint::id(), &jupiter_program_id))
+            .is_err());
+    }
+
+    #[test]
+    fn test_build_instruction_by_direction() {
+        let amm = SolayerRestakingAmm::from_keyed_account(&stake_pool_account(), &amm_context()).unwrap();
+        let jupiter_program_id = Pubkey::new_unique();
+
+        let deposit = amm
+            .build_instruction(&swap_params(native_mint::id(), SOLAYER_SOL, &jupiter_program_id))
+            .unwrap();
+        let withdraw = amm
+            .build_instruction(&swap_params(SOLAYER_SOL, native_mint::id(), &jupiter_program_id))
+            .unwrap();
+
+        assert_eq!(deposit.program_id, spl_stake_pool::id());
+        assert_eq!(withdraw.program_id, spl_stake_pool::id());
+        assert_ne!(deposit.data[0], withdraw.data[0]);
+    }
+
+    #[test]
+    fn test_withdraw_sol_pays_the_owner_wallet() {
+        let amm = SolayerRestakingAmm::from_keyed_account(&stake_pool_account(), &amm_context()).unwrap();
+        let jupiter_program_id = Pubkey::new_unique();
+        let swap_params = swap_params(SOLAYER_SOL, native_mint::id(), &jupiter_program_id);
+
+        let withdraw = amm.build_instruction(&swap_params).unwrap();
+        // stake pool, withdraw authority, user transfer authority, pool tokens from, reserve, lamports to
+        let lamports_to = &withdraw.accounts[5];
+        assert_eq!(lamports_to.pubkey, swap_params.token_transfer_authority);
+        assert!(lamports_to.is_writable);
+        assert!(!withdraw
+            .accounts
+            .iter()
+            .any(|meta| meta.pubkey == swap_params.destination_token_account));
+    }
+
+    #[test]
+    fn test_inactive_without_swap_metas() {
+        let amm = SolayerRestakingAmm::from_keyed_account(&stake_pool_account(), &amm_context()).unwrap();
+        assert!(!amm.is_active());
+    }
+
+    #[test]
+    fn test_get_swap_and_account_metas_refuses_stake_dex_variants() {
+        let amm = SolayerRestakingAmm::from_keyed_account(&stake_pool_account(), &amm_context()).unwrap();
+        let jupiter_program_id = Pubkey::new_unique();
+
+        let err = amm
+            .get_swap_and_account_metas(&swap_params(native_mint::id(), SOLAYER_SOL, &jupiter_program_id))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<SolayerRestakingError>(),
+            Some(&SolayerRestakingError::NoSwapVariant("DepositSol"))
+        );
+    }
+
+    #[test]
+    fn test_stake_pool_errors_are_typed() {
+        let mut keyed_account = stake_pool_account();
+        keyed_account.account.data.clear();
+        let err = SolayerRestakingAmm::from_keyed_account(&keyed_account, &amm_context())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<SolayerRestakingError>(),
+            Some(SolayerRestakingError::InvalidStakePool(_))
+        ));
+
+        let other_mint = Pubkey::new_unique();
+        let mut keyed_account = stake_pool_account();
+        let mut state = try_from_slice_unchecked::<StakePool>(&keyed_account.account.data).unwrap();
+        state.pool_mint = other_mint;
+        keyed_account.account.data = borsh::to_vec(&state).unwrap();
+        let err = SolayerRestakingAmm::from_keyed_account(&keyed_account, &amm_context())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<SolayerRestakingError>(),
+            Some(&SolayerRestakingError::UnexpectedPoolMint(other_mint))
+        );
+
+        let keyed_account = stake_pool_account();
+        let mut amm = SolayerRestakingAmm::from_keyed_account(&keyed_account, &amm_context()).unwrap();
+        let err = amm.update(&AccountMap::default()).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SolayerRestakingError>(),
+            Some(&SolayerRestakingError::MissingStakePool(keyed_account.key))
+        );
+    }
+}
@@ -0,0 +1,6 @@
+pub mod amms;
+pub use amms::amm;
+pub use amms::error;
+pub use amms::instruction;
+pub use amms::registry;
+pub use amms::restaking;
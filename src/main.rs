@@ -1,4 +1,3 @@
-mod amms;
 fn main() {
     println!("Hello, world!");
 }
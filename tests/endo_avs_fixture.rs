@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use jupiter_amm_integration::amm::{
+    unpack_swap_state, SolayerEndoAVSAmm, SolayerEndoAVSAmmBuilder, SwapState, BYBIT_AVS_ADDRESS, BYBIT_AVS_MINT,
+    SOLAYER_DELEGATION_PROGRAM, SOLAYER_SOL, SWAP_V1_VERSION,
+};
+use jupiter_amm_integration::error::SolayerAmmError;
+use jupiter_amm_interface::{Amm, AmmContext, ClockRef, KeyedAccount, KeyedUiAccount, QuoteParams, SwapMode};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_swap::solana_program::program_pack::Pack;
+use spl_token_swap::state::SwapV1;
+
+// Fixtures follow the `getAccountInfo` JSON layout: the version byte followed by a packed SwapV1.
+// synthetic_endo_avs_pool.json is hand-packed, not dumped from mainnet: it pairs sSOL with the
+// Bybit AVS mint, its pool key and token accounts are placeholders.
+fn load_keyed_account(name: &str) -> KeyedAccount {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(format!("{name}.json"));
+    let json = fs::read_to_string(&path).unwrap();
+    let keyed_ui_account: KeyedUiAccount = serde_json::from_str(&json).unwrap();
+    keyed_ui_account.try_into().unwrap()
+}
+
+fn amm_context() -> AmmContext {
+    AmmContext {
+        clock_ref: ClockRef::default(),
+    }
+}
+
+#[test]
+fn test_from_keyed_account_bybit_fixture() {
+    let keyed_account = load_keyed_account("synthetic_endo_avs_pool");
+    let amm = SolayerEndoAVSAmm::from_keyed_account(&keyed_account, &amm_context()).unwrap();
+
+    assert_eq!(amm.key(), pubkey!("7sZvarqSyxMwqtf4z81aCf6SyCxucq65B9KMiNyffDGg"));
+    assert_eq!(amm.get_reserve_mints(), vec![SOLAYER_SOL, BYBIT_AVS_MINT]);
+    assert_eq!(amm.program_id(), SOLAYER_DELEGATION_PROGRAM);
+    assert_eq!(amm.label(), "Solayer (Bybit AVS)");
+    assert_eq!(
+        amm.get_accounts_to_update()[..2],
+        [
+            pubkey!("56oSLsQFtxKHxVHcJ1Eq1zky2p2wnHvW3RR3umNSTLtJ"),
+            pubkey!("EcVJPybLJriQTDFtQosTUsMvdiMuTwL6z4tJm4vQzsYs"),
+        ]
+    );
+}
+
+// Delegating then undelegating loses exactly the fees of both legs, whatever they are
+fn assert_round_trip(amm: &SolayerEndoAVSAmm) {
+    let quote = |amount, input_mint, output_mint| {
+        amm.quote(&QuoteParams {
+            amount,
+            input_mint,
+            output_mint,
+            swap_mode: SwapMode::ExactIn,
+        })
+        .unwrap()
+    };
+
+    for amount in [1, 2, 999, 1_000_000_000, 123_456_789_012, u64::MAX / 2, u64::MAX] {
+        let delegate = quote(amount, SOLAYER_SOL, BYBIT_AVS_MINT);
+        // 1:1 both ways, so only the fees of each leg are lost
+        assert_eq!(delegate.out_amount, amount - delegate.fee_amount, "delegate {amount}");
+        // a tiny amount can go entirely to fees, leaving nothing to undelegate
+        if delegate.out_amount == 0 {
+            continue;
+        }
+
+        let undelegate = quote(delegate.out_amount, BYBIT_AVS_MINT, SOLAYER_SOL);
+        assert_eq!(
+            undelegate.out_amount,
+            amount - delegate.fee_amount - undelegate.fee_amount,
+            "undelegate after delegating {amount}"
+        );
+    }
+}
+
+// Registers the Bybit endoAVS explicitly, so quotes resolve without the built-in mainnet set too
+fn bybit_builder() -> SolayerEndoAVSAmmBuilder {
+    SolayerEndoAVSAmmBuilder::new().avs_overrides(HashMap::from([(BYBIT_AVS_MINT, BYBIT_AVS_ADDRESS)]))
+}
+
+// The fixture pool with its trade fee replaced
+fn fixture_state_with_trade_fee(numerator: u64, denominator: u64) -> SwapV1 {
+    let keyed_account = load_keyed_account("synthetic_endo_avs_pool");
+    let mut state = SwapV1::unpack(&keyed_account.account.data[1..]).unwrap();
+    state.fees.trade_fee_numerator = numerator;
+    state.fees.trade_fee_denominator = denominator;
+    state
+}
+
+#[test]
+fn test_delegate_then_undelegate_round_trip() {
+    let keyed_account = load_keyed_account("synthetic_endo_avs_pool");
+    let amm = bybit_builder().build(&keyed_account, &amm_context()).unwrap();
+
+    assert_round_trip(&amm);
+}
+
+#[test]
+fn test_delegate_then_undelegate_round_trip_with_a_trade_fee() {
+    let state = fixture_state_with_trade_fee(25, 10_000);
+    let amm = bybit_builder().build_with_state(Pubkey::new_unique(), state, SOLAYER_DELEGATION_PROGRAM, &amm_context());
+
+    assert_round_trip(&amm);
+    let quote = amm
+        .quote(&QuoteParams {
+            amount: 1_000_000_000,
+            input_mint: SOLAYER_SOL,
+            output_mint: BYBIT_AVS_MINT,
+            swap_mode: SwapMode::ExactIn,
+        })
+        .unwrap();
+    assert_eq!(quote.fee_amount, 2_500_000);
+}
+
+#[test]
+fn test_delegate_then_undelegate_round_trip_with_an_avs_fee() {
+    let keyed_account = load_keyed_account("synthetic_endo_avs_pool");
+    let amm = bybit_builder()
+        .avs_fee_bps(HashMap::from([(BYBIT_AVS_MINT, 30)]))
+        .unwrap()
+        .build(&keyed_account, &amm_context())
+        .unwrap();
+
+    assert_round_trip(&amm);
+
+    // both fees stack on each leg
+    let state = fixture_state_with_trade_fee(25, 10_000);
+    let amm = bybit_builder()
+        .avs_fee_bps(HashMap::from([(BYBIT_AVS_MINT, 30)]))
+        .unwrap()
+        .build_with_state(Pubkey::new_unique(), state, SOLAYER_DELEGATION_PROGRAM, &amm_context());
+    assert_round_trip(&amm);
+}
+
+#[test]
+fn test_unpack_swap_state_tags_the_version() {
+    let keyed_account = load_keyed_account("synthetic_endo_avs_pool");
+    let state = unpack_swap_state(&keyed_account.key, &keyed_account.account.data).unwrap();
+
+    assert!(matches!(state, SwapState::V1(_)));
+    assert_eq!(state.version(), SWAP_V1_VERSION);
+    assert_eq!(
+        [state.token_a_mint(), state.token_b_mint()],
+        [SOLAYER_SOL, BYBIT_AVS_MINT]
+    );
+    assert_eq!(state.pack(), keyed_account.account.data);
+}
+
+// No V2 layout is published yet, so a V2-tagged account must be refused rather than read as V1
+#[test]
+fn test_unpack_swap_state_refuses_an_unknown_version() {
+    let keyed_account = load_keyed_account("synthetic_endo_avs_pool");
+    let mut data = keyed_account.account.data.clone();
+    data[0] = 2;
+
+    assert_eq!(
+        unpack_swap_state(&keyed_account.key, &data),
+        Err(SolayerAmmError::UnsupportedVersion {
+            version: 2,
+            expected: SWAP_V1_VERSION,
+        })
+    );
+}
+
+// The mock sender answers without any IO, so a plain executor drives `fetch`
+#[cfg(feature = "rpc")]
+#[test]
+fn test_fetch_from_a_mocked_rpc_client() {
+    use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+    use solana_rpc_client_api::request::RpcRequest;
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/synthetic_endo_avs_pool.json");
+    let mut account: serde_json::Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    let key: Pubkey = account["pubkey"].as_str().unwrap().parse().unwrap();
+    account.as_object_mut().unwrap().remove("pubkey");
+    let client = RpcClient::new_mock_with_mocks(
+        "succeeds".to_string(),
+        HashMap::from([(
+            RpcRequest::GetAccountInfo,
+            serde_json::json!({ "context": { "slot": 1 }, "value": account }),
+        )]),
+    );
+
+    let amm = futures::executor::block_on(SolayerEndoAVSAmm::fetch(&client, key, &amm_context())).unwrap();
+    let expected =
+        SolayerEndoAVSAmm::from_keyed_account(&load_keyed_account("synthetic_endo_avs_pool"), &amm_context()).unwrap();
+    assert_eq!(amm.key(), key);
+    assert_eq!(amm.get_reserve_mints(), expected.get_reserve_mints());
+    assert_eq!(amm.state(), expected.state());
+
+    // the mock answers a missing account with null
+    let err = futures::executor::block_on(SolayerEndoAVSAmm::fetch(&client, key, &amm_context()))
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("AccountNotFound"), "{err}");
+}
+
+#[test]
+fn test_from_account_data_matches_the_keyed_account() {
+    let keyed_account = load_keyed_account("synthetic_endo_avs_pool");
+    let amm = SolayerEndoAVSAmm::from_account_data(
+        keyed_account.key,
+        keyed_account.account.owner,
+        &keyed_account.account.data,
+        &amm_context(),
+    )
+    .unwrap();
+    let expected = SolayerEndoAVSAmm::from_keyed_account(&keyed_account, &amm_context()).unwrap();
+
+    assert_eq!(amm.key(), expected.key());
+    assert_eq!(amm.program_id(), expected.program_id());
+    assert_eq!(amm.state(), expected.state());
+    assert_eq!(amm.get_reserve_mints(), expected.get_reserve_mints());
+    assert_eq!(amm.get_accounts_to_update(), expected.get_accounts_to_update());
+
+    // without the version byte the state is one byte short
+    let err = SolayerEndoAVSAmm::from_account_data(
+        keyed_account.key,
+        keyed_account.account.owner,
+        &keyed_account.account.data[1..],
+        &amm_context(),
+    )
+    .err()
+    .unwrap();
+    assert_eq!(
+        err.downcast_ref::<SolayerAmmError>(),
+        Some(&SolayerAmmError::AccountDataTooShort {
+            expected: 1 + SwapV1::LEN,
+            actual: SwapV1::LEN,
+        })
+    );
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use jupiter_amm_integration::amm::{
+    SolayerEndoAVSAmmBuilder, BYBIT_AVS_ADDRESS, BYBIT_AVS_MINT, OKX_AVS_ADDRESS, OKX_AVS_MINT,
+    SOLAYER_DELEGATION_PROGRAM, SOLAYER_SOL, SWAP_V1_VERSION,
+};
+use jupiter_amm_integration::error::SolayerAmmError;
+use jupiter_amm_integration::registry::SolayerAmmRegistry;
+use jupiter_amm_interface::{Amm, AmmContext, ClockRef, KeyedAccount};
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_swap::solana_program::program_pack::Pack;
+use spl_token_swap::solana_program::pubkey::Pubkey as SwapPubkey;
+use spl_token_swap::state::SwapV1;
+
+fn swap_pubkey(pubkey: Pubkey) -> SwapPubkey {
+    SwapPubkey::new_from_array(pubkey.to_bytes())
+}
+
+// A pool pairing sSOL with `avs_mint`, stored the way the delegation program writes it
+fn pool(avs_mint: Pubkey) -> KeyedAccount {
+    let state = SwapV1 {
+        is_initialized: true,
+        token_a: swap_pubkey(Pubkey::new_unique()),
+        token_b: swap_pubkey(Pubkey::new_unique()),
+        token_a_mint: swap_pubkey(SOLAYER_SOL),
+        token_b_mint: swap_pubkey(avs_mint),
+        ..SwapV1::default()
+    };
+    let mut data = vec![0; 1 + SwapV1::LEN];
+    data[0] = SWAP_V1_VERSION;
+    state.pack_into_slice(&mut data[1..]);
+    KeyedAccount {
+        key: Pubkey::new_unique(),
+        account: Account {
+            lamports: 0,
+            data,
+            owner: SOLAYER_DELEGATION_PROGRAM,
+            executable: false,
+            rent_epoch: 0,
+        },
+        params: None,
+    }
+}
+
+fn amm_context() -> AmmContext {
+    AmmContext {
+        clock_ref: ClockRef::default(),
+    }
+}
+
+#[test]
+fn test_quote_best_routes_to_the_pool_holding_the_pair() {
+    let bybit = pool(BYBIT_AVS_MINT);
+    let okx = pool(OKX_AVS_MINT);
+    // registered explicitly so the test holds without the built-in mainnet set too
+    let builder = SolayerEndoAVSAmmBuilder::new().avs_overrides(HashMap::from([
+        (BYBIT_AVS_MINT, BYBIT_AVS_ADDRESS),
+        (OKX_AVS_MINT, OKX_AVS_ADDRESS),
+    ]));
+    let registry = SolayerAmmRegistry::with_builder(&[bybit.clone(), okx.clone()], &amm_context(), builder).unwrap();
+    assert_eq!(registry.amms().len(), 2);
+
+    let (amm, quote) = registry.quote_best(SOLAYER_SOL, OKX_AVS_MINT, 1_000).unwrap();
+    assert_eq!(amm.key(), okx.key);
+    assert_eq!((quote.in_amount, quote.out_amount), (1_000, 1_000));
+
+    let (amm, quote) = registry.quote_best(BYBIT_AVS_MINT, SOLAYER_SOL, 2_000).unwrap();
+    assert_eq!(amm.key(), bybit.key);
+    assert_eq!((quote.in_amount, quote.out_amount), (2_000, 2_000));
+
+    assert_eq!(
+        registry.get(&SOLAYER_SOL, &BYBIT_AVS_MINT).map(|amm| amm.key()),
+        Some(bybit.key)
+    );
+}
+
+#[test]
+fn test_quote_best_without_a_pool_is_an_error() {
+    let registry = SolayerAmmRegistry::new(&[pool(BYBIT_AVS_MINT)], &amm_context()).unwrap();
+    let err = registry.quote_best(SOLAYER_SOL, OKX_AVS_MINT, 1_000).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<SolayerAmmError>(),
+        Some(&SolayerAmmError::NoRoutablePool {
+            input_mint: SOLAYER_SOL,
+            output_mint: OKX_AVS_MINT,
+        })
+    );
+}
+
+#[test]
+fn test_quote_best_returns_the_pool_error() {
+    let builder = SolayerEndoAVSAmmBuilder::new()
+        .avs_overrides(HashMap::from([(BYBIT_AVS_MINT, BYBIT_AVS_ADDRESS)]))
+        .min_amount(10_000);
+    let registry = SolayerAmmRegistry::with_builder(&[pool(BYBIT_AVS_MINT)], &amm_context(), builder).unwrap();
+    let err = registry.quote_best(SOLAYER_SOL, BYBIT_AVS_MINT, 1_000).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<SolayerAmmError>(),
+        Some(&SolayerAmmError::AmountBelowMinimum {
+            amount: 1_000,
+            min_amount: 10_000,
+        })
+    );
+}
+
+#[test]
+fn test_routable_pairs_aggregate_across_pools() {
+    let bybit = pool(BYBIT_AVS_MINT);
+    // the same pair listed twice only routes once
+    let registry = SolayerAmmRegistry::new(&[bybit.clone(), bybit, pool(OKX_AVS_MINT)], &amm_context()).unwrap();
+    let mut expected = vec![
+        (SOLAYER_SOL, BYBIT_AVS_MINT),
+        (BYBIT_AVS_MINT, SOLAYER_SOL),
+        (SOLAYER_SOL, OKX_AVS_MINT),
+        (OKX_AVS_MINT, SOLAYER_SOL),
+    ];
+    expected.sort();
+    assert_eq!(registry.routable_pairs(), expected);
+}
+
+#[cfg(feature = "registry")]
+#[test]
+fn test_avs_overrides_from_json_path_resolve_an_entry() {
+    use std::path::PathBuf;
+
+    use solana_sdk::pubkey;
+
+    // the fixture addresses are test placeholders, not deployed endoAVS accounts
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let overrides = SolayerAmmRegistry::avs_overrides_from_json_path(fixtures.join("avs_registry.json")).unwrap();
+    assert_eq!(overrides.len(), 2);
+    let placeholder_bybit = pubkey!("2dFX61fNTPhmEm84sN2uwKp2VmNE2NbzVbTNMcamWEiJ");
+    assert_eq!(overrides.get(&BYBIT_AVS_MINT), Some(&placeholder_bybit));
+
+    // the file overrides the built-in address when building the pools
+    let registry = SolayerAmmRegistry::from_json_path(
+        fixtures.join("avs_registry.json"),
+        &[pool(BYBIT_AVS_MINT)],
+        &amm_context(),
+    )
+    .unwrap();
+    assert_eq!(
+        registry.amms()[0].endo_avs_address(&BYBIT_AVS_MINT),
+        Ok(placeholder_bybit)
+    );
+
+    // a bad pubkey names its entry
+    let err = SolayerAmmRegistry::from_json_path(
+        fixtures.join("avs_registry_invalid.json"),
+        &[pool(BYBIT_AVS_MINT)],
+        &amm_context(),
+    )
+    .err()
+    .unwrap();
+    assert!(
+        format!("{err:#}").contains("entry 0 (Broken): invalid mint not-a-pubkey"),
+        "{err:#}"
+    );
+}